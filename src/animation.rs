@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Context, Result};
+use image::codecs::gif::GifEncoder;
+use image::imageops;
+use image::{Delay, Frame, RgbImage, Rgba, RgbaImage};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+// сколько пикселей всех кадров вместе берётся для общей палитры
+// на всех пикселях всех кадров NeuQuant работает минутами, а палитра почти не меняется
+const PALETTE_SAMPLES: usize = 64 * 1024;
+
+// кадры клипа, из которых собирается анимированный GIF
+// у GIF не больше 256 цветов, и если у каждого кадра своя палитра, то соседние кадры
+// квантуются по-разному и картинка мерцает, поэтому палитра одна на весь клип:
+// она строится по выборке пикселей всех кадров, а кадры переводятся в неё с дизерингом
+pub struct Animation {
+    frames: Vec<RgbImage>,
+    fps: f64,
+}
+
+impl Animation {
+    // кадры идут с постоянной частотой fps
+    // задержка в GIF считается в сотых долях секунды, а браузеры меньше 2 сотых
+    // показывают как 10, так что больше 50 кадров в секунду не бывает
+    pub fn new(fps: f64) -> Result<Animation> {
+        if !(fps > 0.0 && fps <= 50.0) {
+            return Err(anyhow!("gif fps must be between 0 and 50, not {}", fps));
+        }
+        Ok(Animation {
+            frames: Vec::new(),
+            fps,
+        })
+    }
+
+    // кадры должны быть одного размера
+    pub fn push(&mut self, frame: RgbImage) {
+        self.frames.push(frame);
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // пишет GIF в path, WebP в image 0.23 можно только читать
+    // повтор image 0.23 не записывает, поэтому анимация проигрывается один раз
+    pub fn save<P: AsRef<Path>>(self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gif") => {}
+            _ => return Err(anyhow!("{} is not a .gif file", path.display())),
+        }
+        if self.frames.is_empty() {
+            return Err(anyhow!("no frames to write to {}", path.display()));
+        }
+
+        let palette = self.palette();
+        let file =
+            File::create(path).with_context(|| format!("couldn't create {}", path.display()))?;
+        let mut encoder = GifEncoder::new(BufWriter::new(file));
+
+        // одинаковые после квантования кадры (статичные сцены) склеиваются в один подольше
+        let mut pending: Option<(RgbaImage, u32)> = None;
+        for (index, frame) in self.frames.iter().enumerate() {
+            let mut image = rgba(frame);
+            imageops::dither(&mut image, &palette);
+            let delay = self.delay(index);
+            pending = match pending {
+                Some((previous, previous_delay)) if previous == image => {
+                    Some((previous, previous_delay + delay))
+                }
+                Some((previous, previous_delay)) => {
+                    encoder.encode_frame(frame_with_delay(previous, previous_delay))?;
+                    Some((image, delay))
+                }
+                None => Some((image, delay)),
+            };
+        }
+        if let Some((image, delay)) = pending {
+            encoder.encode_frame(frame_with_delay(image, delay))?;
+        }
+        Ok(())
+    }
+
+    // задержка кадра index в миллисекундах, кратная 10, потому что в GIF она в сотых
+    // считается от начала клипа, чтобы ошибки округления не накапливались
+    fn delay(&self, index: usize) -> u32 {
+        let at = |index: usize| (index as f64 * 100.0 / self.fps).round() as u32;
+        (at(index + 1) - at(index)).max(2) * 10
+    }
+
+    // NeuQuant на 256 цветов по равномерной выборке пикселей всех кадров
+    // color_quant у нас не в зависимостях, а image отдаёт его только через устаревший nq
+    #[allow(deprecated)]
+    fn palette(&self) -> image::math::nq::NeuQuant {
+        let per_frame = (PALETTE_SAMPLES / self.frames.len()).max(1);
+        let mut samples = Vec::with_capacity(per_frame * self.frames.len() * 4);
+        for frame in &self.frames {
+            let pixels = (frame.width() * frame.height()) as usize;
+            let step = (pixels / per_frame).max(1);
+            for pixel in frame.pixels().step_by(step) {
+                samples.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]);
+            }
+        }
+        // 1 это самый медленный и точный режим, выборка и так небольшая
+        image::math::nq::NeuQuant::new(1, 256, &samples)
+    }
+}
+
+fn rgba(frame: &RgbImage) -> RgbaImage {
+    RgbaImage::from_fn(frame.width(), frame.height(), |x, y| {
+        let pixel = frame.get_pixel(x, y);
+        Rgba([pixel[0], pixel[1], pixel[2], 255])
+    })
+}
+
+fn frame_with_delay(image: RgbaImage, delay: u32) -> Frame {
+    Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(delay, 1))
+}
@@ -0,0 +1,20 @@
+use anyhow::{anyhow, Result};
+
+// помощники для разбора командной строки в бинарниках
+// каждый бинарник разбирает свои аргументы сам, а значения опций достаёт отсюда
+
+// значение опции, которое идёт следующим аргументом: --scale 1280x720
+pub fn value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String> {
+    args.next()
+        .ok_or_else(|| anyhow!("option {} requires a value", option))
+}
+
+// неотрицательное время в секундах: --start 90.5
+pub fn seconds(args: &mut impl Iterator<Item = String>, option: &str) -> Result<f64> {
+    let value = value(args, option)?;
+    value
+        .parse()
+        .ok()
+        .filter(|seconds: &f64| *seconds >= 0.0)
+        .ok_or_else(|| anyhow!("option {} expects seconds, not {}", option, value))
+}
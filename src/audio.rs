@@ -0,0 +1,11 @@
+use sdl2::audio::AudioQueue;
+use std::thread;
+use std::time::Duration;
+
+// ждём пока устройство доиграет всё что лежит в его очереди,
+// иначе при выходе сразу после конца файла обрезается хвост звука
+pub fn wait_until_played(device: &AudioQueue<i16>) {
+    while device.size() > 0 {
+        thread::sleep(Duration::from_millis(10));
+    }
+}
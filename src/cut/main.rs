@@ -0,0 +1,195 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use anyhow::{anyhow, Context, Result};
+use ffmpeg::format::{input, output};
+use ffmpeg::media::Type;
+use ffmpeg::{codec, encoder, rescale, Rational};
+use fftut::args::seconds;
+use fftut::decode::start_time;
+use std::env;
+
+// вырезает кусок файла в новый файл без перекодирования, как ffmpeg -ss -to -c copy
+// cut movie.mkv clip.mp4 --start 90 --end 120
+// пакеты копируются как есть, поэтому начать можно только с ключевого кадра:
+// клип начинается с ближайшего ключевого кадра видео до --start
+fn main() -> Result<()> {
+    // регистриует все доступные форматы, кодеки и т.д.
+    ffmpeg::init().unwrap();
+
+    let options = Options::parse()?;
+
+    // открываем указанный input, по сути читает header файла и информацию о потоках
+    let mut ictx = input(&options.input)?;
+    // формат выходного файла выбирается по расширению
+    let mut octx = output(&options.output)?;
+
+    // копируем видео, аудио и субтитры, остальные потоки (данные, вложения) контейнер
+    // назначения может и не принять
+    // stream_mapping это номер выходного потока для каждого входного
+    let mut stream_mapping = vec![None; ictx.nb_streams() as usize];
+    let mut input_time_bases = vec![Rational::new(0, 1); ictx.nb_streams() as usize];
+    // конца клипа ждём только у видео и аудио: у субтитров пакеты редкие,
+    // и после --end их может вообще не быть, тогда файл читался бы до конца
+    let mut waits_for_end = vec![false; ictx.nb_streams() as usize];
+    let mut output_index = 0;
+    for (input_index, stream) in ictx.streams().enumerate() {
+        let medium = stream.parameters().medium();
+        if medium != Type::Video && medium != Type::Audio && medium != Type::Subtitle {
+            continue;
+        }
+        stream_mapping[input_index] = Some(output_index);
+        input_time_bases[input_index] = stream.time_base();
+        waits_for_end[input_index] = medium != Type::Subtitle;
+        output_index += 1;
+
+        // кодек не нужен, параметры потока копируются из входного файла
+        let mut output_stream = octx.add_stream(encoder::find(codec::Id::None))?;
+        output_stream.set_parameters(stream.parameters());
+        // тег кодека у разных контейнеров свой, пусть муксер выберет его сам
+        unsafe {
+            (*output_stream.parameters().as_mut_ptr()).codec_tag = 0;
+        }
+    }
+    if output_index == 0 {
+        return Err(anyhow!("{} has no streams to copy", options.input));
+    }
+    octx.set_metadata(ictx.metadata().to_owned());
+
+    // по ключевым кадрам видео выбирается начало клипа, без видео начать можно с любого пакета
+    let video_stream_index = ictx
+        .streams()
+        .best(Type::Video)
+        .map(|stream| stream.index());
+
+    // --start и --end считаются от начала файла, а время пакетов от нуля
+    let file_start = start_time(&ictx);
+    let origin = file_start as f64 * f64::from(rescale::TIME_BASE);
+
+    // seek без индекса потока ждёт время в AV_TIME_BASE и попадает на ключевой кадр до позиции
+    let start = file_start + (options.start / f64::from(rescale::TIME_BASE)).round() as i64;
+    ictx.seek(start, ..start)
+        .with_context(|| format!("couldn't seek to {:.3}s", options.start))?;
+
+    octx.write_header()
+        .with_context(|| format!("couldn't write header of {}", options.output))?;
+
+    // время первого ключевого кадра в секундах от начала файла,
+    // с него в выходном файле начинается отсчёт
+    let mut clip_start: Option<f64> = None;
+    // видео и аудио потоки, которые уже дошли до --end
+    let mut finished = vec![false; ictx.nb_streams() as usize];
+    let mut packets_written = 0;
+
+    // читаем все пакеты из потока через av_read_frame()
+    for (stream, mut packet) in ictx.packets() {
+        let input_index = stream.index();
+        let output_index = match stream_mapping[input_index] {
+            Some(output_index) => output_index,
+            None => continue,
+        };
+        let time_base = input_time_bases[input_index];
+        let seconds = match packet.pts().or_else(|| packet.dts()) {
+            Some(ts) => ts as f64 * f64::from(time_base) - origin,
+            None => continue,
+        };
+
+        let clip_start = match clip_start {
+            Some(clip_start) => clip_start,
+            // ждём первый ключевой кадр видео, всё что до него без него не декодируется
+            None if video_stream_index.map_or(true, |index| index == input_index)
+                && packet.is_key() =>
+            {
+                clip_start = Some(seconds);
+                seconds
+            }
+            None => continue,
+        };
+        // аудио из-за перемежения пакетов бывает чуть раньше ключевого кадра, его отбрасываем,
+        // а видео после ключевого кадра нужно всё: B-кадры раньше него по pts, но без них
+        // не декодируются следующие
+        if Some(input_index) != video_stream_index && seconds < clip_start {
+            continue;
+        }
+        if seconds >= options.end {
+            finished[input_index] = true;
+            let done = waits_for_end
+                .iter()
+                .zip(&finished)
+                .all(|(&waits, &finished)| !waits || finished);
+            if done {
+                break;
+            }
+            continue;
+        }
+
+        // сдвигаем время так, чтобы клип начинался с нуля, и переводим в time base выходного потока
+        let offset = ((origin + clip_start) / f64::from(time_base)).round() as i64;
+        packet.set_pts(packet.pts().map(|pts| pts - offset));
+        packet.set_dts(packet.dts().map(|dts| dts - offset));
+        let output_time_base = octx.stream(output_index).unwrap().time_base();
+        packet.rescale_ts(time_base, output_time_base);
+        packet.set_position(-1);
+        packet.set_stream(output_index);
+        packet
+            .write_interleaved(&mut octx)
+            .context("couldn't write packet")?;
+        packets_written += 1;
+    }
+
+    octx.write_trailer()
+        .with_context(|| format!("couldn't finish {}", options.output))?;
+
+    match clip_start {
+        Some(clip_start) => println!(
+            "{}: {} packets from {:.3}s to {:.3}s",
+            options.output, packets_written, clip_start, options.end
+        ),
+        None => println!(
+            "{}: nothing to cut after {:.3}s",
+            options.output, options.start
+        ),
+    }
+    Ok(())
+}
+
+// параметры командной строки
+struct Options {
+    input: String,
+    output: String,
+    // секунды от начала файла
+    start: f64,
+    end: f64,
+}
+
+impl Options {
+    fn parse() -> Result<Options> {
+        let mut files = Vec::new();
+        let mut start = 0.0;
+        let mut end = f64::INFINITY;
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--start" => start = seconds(&mut args, &arg)?,
+                "--end" => end = seconds(&mut args, &arg)?,
+                _ if arg.starts_with("--") => return Err(anyhow!("unknown option {}", arg)),
+                _ => files.push(arg),
+            }
+        }
+
+        if files.len() != 2 {
+            return Err(anyhow!("usage: cut <input> <output> [--start s] [--end s]"));
+        }
+        if end <= start {
+            return Err(anyhow!("--end must be after --start"));
+        }
+        let output = files.pop().unwrap();
+        let input = files.pop().unwrap();
+        Ok(Options {
+            input,
+            output,
+            start,
+            end,
+        })
+    }
+}
@@ -0,0 +1,236 @@
+use crate::scale::Scaling;
+use anyhow::{anyhow, Result};
+use ffmpeg::codec::decoder::{self, Opened};
+use ffmpeg::codec::packet::Packet;
+use ffmpeg::codec::threading;
+use ffmpeg::format::{context::Input, input, stream::Stream, Pixel};
+use ffmpeg::media::Type;
+use ffmpeg::util::frame::video::Video;
+use ffmpeg::{rescale, Rescale};
+use std::ops::DerefMut;
+use std::path::Path;
+
+// что делать с пакетом, который не получилось декодировать
+#[derive(Clone, Copy, PartialEq)]
+pub enum ErrorPolicy {
+    // пропустить пакет и декодировать дальше
+    Skip,
+    // остановиться на первой же ошибке, как раньше
+    Abort,
+}
+
+// открывает декодеры с общими для всех бинарников настройками
+// пока это только потоки декодирования: сколько их и кадровые они или по слайсам
+// по умолчанию ничего не меняется и ffmpeg декодирует в один поток
+#[derive(Clone, Copy, Default)]
+pub struct DecoderBuilder {
+    // 0 значит выбрать по числу ядер
+    threads: Option<usize>,
+    kind: Option<threading::Type>,
+}
+
+impl DecoderBuilder {
+    pub fn new() -> DecoderBuilder {
+        DecoderBuilder::default()
+    }
+
+    pub fn threads(mut self, count: usize) -> DecoderBuilder {
+        self.threads = Some(count);
+        self
+    }
+
+    // кадровые потоки декодируют несколько кадров сразу и добавляют задержку на кадр на поток,
+    // слайсовые делят один кадр, но не у всех кодеков и файлов в кадре больше одного слайса
+    pub fn threading(mut self, kind: threading::Type) -> DecoderBuilder {
+        self.kind = Some(kind);
+        self
+    }
+
+    // применяет одну опцию командной строки, для бинарников со своим разбором аргументов
+    pub fn option(self, name: &str, value: &str) -> Result<DecoderBuilder> {
+        match name {
+            "--threads" => {
+                let count = value
+                    .parse()
+                    .map_err(|_| anyhow!("invalid thread count {}", value))?;
+                Ok(self.threads(count))
+            }
+            "--threading" => match value {
+                "frame" => Ok(self.threading(threading::Type::Frame)),
+                "slice" => Ok(self.threading(threading::Type::Slice)),
+                _ => Err(anyhow!("threading must be frame or slice, not {}", value)),
+            },
+            _ => Err(anyhow!("unknown decoder option {}", name)),
+        }
+    }
+
+    // настройки потоков надо выставить до avcodec_open2(), то есть до .video() и .audio()
+    fn decoder(&self, stream: &Stream) -> decoder::Decoder {
+        let mut decoder = stream.codec().decoder();
+        match self.kind {
+            Some(kind) => decoder.set_threading(threading::Config {
+                kind,
+                count: self.threads.unwrap_or(0),
+                safe: false,
+            }),
+            // только число потоков: тип оставляем ffmpeg, он выберет кадровые, если кодек умеет
+            None => {
+                if let Some(count) = self.threads {
+                    unsafe {
+                        (*decoder.as_mut_ptr()).thread_count = count as i32;
+                    }
+                }
+            }
+        }
+        decoder
+    }
+
+    pub fn video(&self, stream: &Stream) -> Result<decoder::Video, ffmpeg::Error> {
+        self.decoder(stream).video()
+    }
+
+    pub fn audio(&self, stream: &Stream) -> Result<decoder::Audio, ffmpeg::Error> {
+        self.decoder(stream).audio()
+    }
+}
+
+// отправляет пакеты в декодер и считает битые
+// один повреждённый пакет в записи эфира не должен останавливать всё декодирование
+pub struct DecodeErrors {
+    policy: ErrorPolicy,
+    skipped: usize,
+}
+
+impl DecodeErrors {
+    pub fn new(policy: ErrorPolicy) -> DecodeErrors {
+        DecodeErrors { policy, skipped: 0 }
+    }
+
+    // avcodec_send_packet(), но битый пакет при ErrorPolicy::Skip пропускается:
+    // тогда возвращается Ok(Some(ошибка)), чтобы вызывающий мог о ней сообщить
+    pub fn send_packet(
+        &mut self,
+        decoder: &mut Opened,
+        packet: &Packet,
+    ) -> Result<Option<ffmpeg::Error>, ffmpeg::Error> {
+        match decoder.send_packet(packet) {
+            Ok(()) => Ok(None),
+            Err(error) if self.policy == ErrorPolicy::Skip => {
+                self.skipped += 1;
+                Ok(Some(error))
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+// в конце файла декодер ещё держит у себя несколько кадров (например из-за B-кадров)
+// send_eof() переводит его в режим опустошения, а receive забирает всё что осталось
+// receive может возвращать и свою ошибку, например когда кадры сразу рисуются
+pub fn drain<D, F, E>(decoder: &mut D, mut receive: F) -> Result<(), E>
+where
+    D: DerefMut<Target = Opened>,
+    F: FnMut(&mut D) -> Result<(), E>,
+    E: From<ffmpeg::Error>,
+{
+    decoder.send_eof()?;
+    receive(decoder)
+}
+
+// время начала файла в AV_TIME_BASE, у MPEG-TS и записей эфира оно далеко не ноль,
+// а секунды "от начала файла" в опциях отсчитываются от него
+// если контейнер его не знает, то 0
+pub fn start_time(ictx: &Input) -> i64 {
+    match ictx.start_time() {
+        ffmpeg::ffi::AV_NOPTS_VALUE => 0,
+        start_time => start_time,
+    }
+}
+
+// достаёт кадр, который показывается через seconds от начала файла, и переводит его в format
+// seek попадает только на ключевые кадры, поэтому перематываем на ключевой кадр до нужного
+// момента и декодируем вперёд, пока не дойдём до кадра с нужным pts
+// pts возвращённого кадра остаётся в time base видео потока
+pub fn decode_frame_at<P: AsRef<Path>>(path: P, seconds: f64, format: Pixel) -> Result<Video> {
+    decode_frame_at_with(path, seconds, format, DecoderBuilder::new())
+}
+
+// то же, но с настройками декодера, например с потоками для тяжёлых кодеков
+pub fn decode_frame_at_with<P: AsRef<Path>>(
+    path: P,
+    seconds: f64,
+    format: Pixel,
+    builder: DecoderBuilder,
+) -> Result<Video> {
+    let mut ictx = input(&path)?;
+    let stream = ictx
+        .streams()
+        .best(Type::Video)
+        .ok_or(ffmpeg::Error::StreamNotFound)?;
+    let video_stream_index = stream.index();
+    let time_base = stream.time_base();
+    let mut decoder = builder.video(&stream)?;
+
+    // seconds считаются от начала файла, а pts кадров от нуля
+    let origin = start_time(&ictx) as f64 * f64::from(rescale::TIME_BASE);
+    let target = ((origin + seconds) / f64::from(time_base)).round() as i64;
+    // seek без индекса потока ждёт время в AV_TIME_BASE
+    let position = target.rescale(time_base, rescale::TIME_BASE);
+    ictx.seek(position, ..position)?;
+
+    // последний кадр с pts не больше target, он и показывается в этот момент
+    let mut shown: Option<Video> = None;
+    let mut found = false;
+    let mut receive_until_target =
+        |decoder: &mut ffmpeg::decoder::Video, shown: &mut Option<Video>| -> bool {
+            let mut decoded = Video::empty();
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                // кадр без pts считаем нужным, дальше по нему всё равно ничего не понять
+                let pts = decoded.timestamp().unwrap_or(target);
+                // следующий кадр уже позже нужного момента, значит нужный это предыдущий
+                if pts > target && shown.is_some() {
+                    return true;
+                }
+                *shown = Some(decoded);
+                if pts >= target {
+                    return true;
+                }
+                decoded = Video::empty();
+            }
+            false
+        };
+
+    // одиночный битый пакет не повод не отдать кадр
+    let mut errors = DecodeErrors::new(ErrorPolicy::Skip);
+    for (stream, packet) in ictx.packets() {
+        if stream.index() != video_stream_index {
+            continue;
+        }
+        errors.send_packet(&mut decoder, &packet)?;
+        if receive_until_target(&mut decoder, &mut shown) {
+            found = true;
+            break;
+        }
+    }
+    // момент после последнего кадра или кадр придержан декодером до конца файла
+    if !found {
+        drain(&mut decoder, |decoder| {
+            receive_until_target(decoder, &mut shown);
+            Ok::<(), ffmpeg::Error>(())
+        })?;
+    }
+    let frame = shown.ok_or_else(|| anyhow!("no video frame at {:.3}s", seconds))?;
+
+    // здесь происходит аллокация пустого фрейма куда мы поместим модифицированный фрейм
+    let mut converted = Video::empty();
+    // переводим фрейм в нужный формат sws_scale()
+    Scaling::new()
+        .context(frame.format(), frame.width(), frame.height(), format)?
+        .run(&frame, &mut converted)?;
+    converted.set_pts(frame.timestamp());
+    Ok(converted)
+}
@@ -0,0 +1,10 @@
+extern crate ffmpeg_next as ffmpeg;
+
+pub mod animation;
+pub mod args;
+pub mod audio;
+pub mod decode;
+pub mod playlist;
+pub mod rotation;
+pub mod scale;
+pub mod snapshot;
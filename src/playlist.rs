@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Context as ErrorContext, Result};
+use std::fs;
+use std::path::Path;
+
+// список файлов для проигрывания по порядку
+// .m3u/.m3u8 файлы разворачиваются в те файлы, которые в них перечислены
+pub struct Playlist {
+    items: Vec<String>,
+    current: usize,
+}
+
+impl Playlist {
+    pub fn new(inputs: &[String]) -> Result<Playlist> {
+        let mut items = Vec::new();
+        for input in inputs {
+            if is_m3u(input) {
+                items.extend(read_m3u(input)?);
+            } else {
+                items.push(input.clone());
+            }
+        }
+
+        if items.is_empty() {
+            return Err(anyhow!("playlist is empty"));
+        }
+
+        Ok(Playlist { items, current: 0 })
+    }
+
+    // число файлов после разворачивания плейлистов
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn current(&self) -> &str {
+        &self.items[self.current]
+    }
+
+    // переходит к следующему элементу, false если список закончился
+    pub fn next(&mut self) -> bool {
+        if self.current + 1 < self.items.len() {
+            self.current += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn previous(&mut self) {
+        self.current = self.current.saturating_sub(1);
+    }
+}
+
+fn is_m3u(input: &str) -> bool {
+    let input = input.to_lowercase();
+    input.ends_with(".m3u") || input.ends_with(".m3u8")
+}
+
+// в m3u каждая строка это путь или url, строки начинающиеся с # это комментарии и теги
+fn read_m3u(path: &str) -> Result<Vec<String>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("couldn't read playlist {}", path))?;
+    let base = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    Ok(parse_m3u(&content, base))
+}
+
+// относительные пути считаются от папки base, в которой лежит сам плейлист
+fn parse_m3u(content: &str, base: &Path) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| line.trim().trim_start_matches('\u{feff}'))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            if line.contains("://") || Path::new(line).is_absolute() {
+                line.to_string()
+            } else {
+                base.join(line).to_string_lossy().into_owned()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn m3u_skips_comments_and_blank_lines() {
+        let content = "#EXTM3U\n\n#EXTINF:123,Artist - Title\nsong.mp3\n   \n# comment\n";
+        assert_eq!(parse_m3u(content, Path::new("")), vec!["song.mp3"]);
+    }
+
+    #[test]
+    fn m3u_strips_bom_and_crlf() {
+        let content = "\u{feff}#EXTM3U\r\none.mp4\r\ntwo.mp4\r\n";
+        assert_eq!(
+            parse_m3u(content, Path::new("")),
+            vec!["one.mp4", "two.mp4"]
+        );
+    }
+
+    #[test]
+    fn m3u_resolves_relative_paths_against_playlist_folder() {
+        let base = Path::new("music").join("album");
+        let items = parse_m3u("01.flac\nextra/02.flac\n", &base);
+        assert_eq!(
+            items,
+            vec![
+                base.join("01.flac").to_string_lossy().into_owned(),
+                base.join("extra/02.flac").to_string_lossy().into_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn m3u_keeps_urls_and_absolute_paths() {
+        let absolute = if cfg!(windows) {
+            "C:\\video\\movie.mkv"
+        } else {
+            "/video/movie.mkv"
+        };
+        let content = format!("http://example.com/live.m3u8\n{}\n", absolute);
+        assert_eq!(
+            parse_m3u(&content, Path::new("playlists")),
+            vec!["http://example.com/live.m3u8", absolute]
+        );
+    }
+
+    #[test]
+    fn playlist_expands_m3u_in_place() {
+        let dir = std::env::temp_dir().join(format!("fftut-playlist-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let m3u = dir.join("list.M3U8");
+        fs::write(&m3u, "a.mp4\nb.mp4\n").unwrap();
+
+        let inputs = vec![
+            "first.mkv".to_string(),
+            m3u.to_string_lossy().into_owned(),
+            "last.mkv".to_string(),
+        ];
+        let mut playlist = Playlist::new(&inputs).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut items = vec![playlist.current().to_string()];
+        while playlist.next() {
+            items.push(playlist.current().to_string());
+        }
+        assert_eq!(
+            items,
+            vec![
+                "first.mkv".to_string(),
+                dir.join("a.mp4").to_string_lossy().into_owned(),
+                dir.join("b.mp4").to_string_lossy().into_owned(),
+                "last.mkv".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn playlist_navigation_stops_at_the_ends() {
+        let inputs = vec!["one".to_string(), "two".to_string()];
+        let mut playlist = Playlist::new(&inputs).unwrap();
+        playlist.previous();
+        assert_eq!(playlist.current(), "one");
+        assert!(playlist.next());
+        assert!(!playlist.next());
+        assert_eq!(playlist.current(), "two");
+        playlist.previous();
+        assert_eq!(playlist.current(), "one");
+    }
+
+    #[test]
+    fn empty_playlist_is_an_error() {
+        assert!(Playlist::new(&[]).is_err());
+    }
+}
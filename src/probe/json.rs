@@ -0,0 +1,246 @@
+use std::fmt::{self, Write};
+
+// минимальное JSON значение, ради одного вывода тянуть serde не хочется
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    // порядок ключей сохраняется, так вывод читать удобнее
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn object() -> Value {
+        Value::Object(Vec::new())
+    }
+
+    // добавляет поле в объект, для остальных значений ничего не делает
+    pub fn insert<K: Into<String>, V: Into<Value>>(&mut self, key: K, value: V) {
+        if let Value::Object(fields) = self {
+            fields.push((key.into(), value.into()));
+        }
+    }
+
+    fn write(&self, out: &mut String, indent: usize) -> fmt::Result {
+        match self {
+            Value::Null => out.push_str("null"),
+            Value::Integer(value) => write!(out, "{}", value)?,
+            // NaN и бесконечности в JSON не записать
+            Value::Number(value) if value.is_finite() => write!(out, "{}", value)?,
+            Value::Number(_) => out.push_str("null"),
+            Value::String(value) => write_string(out, value)?,
+            Value::Array(items) if items.is_empty() => out.push_str("[]"),
+            Value::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    new_line(out, indent + 1);
+                    item.write(out, indent + 1)?;
+                }
+                new_line(out, indent);
+                out.push(']');
+            }
+            Value::Object(fields) if fields.is_empty() => out.push_str("{}"),
+            Value::Object(fields) => {
+                out.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    new_line(out, indent + 1);
+                    write_string(out, key)?;
+                    out.push_str(": ");
+                    value.write(out, indent + 1)?;
+                }
+                new_line(out, indent);
+                out.push('}');
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out, 0)?;
+        f.write_str(&out)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Value {
+        Value::Integer(value)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Value {
+        Value::Integer(i64::from(value))
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Value {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Value {
+        Value::Number(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Value {
+        Value::String(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Value {
+        value.map(Into::into).unwrap_or(Value::Null)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(items: Vec<Value>) -> Value {
+        Value::Array(items)
+    }
+}
+
+fn new_line(out: &mut String, indent: usize) {
+    out.push('\n');
+    for _ in 0..indent {
+        out.push_str("  ");
+    }
+}
+
+// кавычки, обратный слеш и управляющие символы экранируются, остальное пишется как есть в UTF-8
+fn write_string(out: &mut String, value: &str) -> fmt::Result {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_are_escaped() {
+        let value = Value::from("say \"hi\"\\\n\r\t\u{1}\u{1f} ok");
+        assert_eq!(
+            value.to_string(),
+            "\"say \\\"hi\\\"\\\\\\n\\r\\t\\u0001\\u001f ok\""
+        );
+    }
+
+    #[test]
+    fn non_ascii_is_written_as_is() {
+        assert_eq!(Value::from("фильм 🎬").to_string(), "\"фильм 🎬\"");
+    }
+
+    #[test]
+    fn non_finite_numbers_are_null() {
+        assert_eq!(Value::from(f64::NAN).to_string(), "null");
+        assert_eq!(Value::from(f64::INFINITY).to_string(), "null");
+        assert_eq!(Value::from(f64::NEG_INFINITY).to_string(), "null");
+    }
+
+    #[test]
+    fn empty_containers() {
+        assert_eq!(Value::object().to_string(), "{}");
+        assert_eq!(Value::from(Vec::<Value>::new()).to_string(), "[]");
+    }
+
+    #[test]
+    fn object_keeps_key_order_and_indents() {
+        let mut value = Value::object();
+        value.insert("b", 1i64);
+        value.insert("a", vec![Value::from(None::<i64>), Value::from("x")]);
+        assert_eq!(
+            value.to_string(),
+            "{\n  \"b\": 1,\n  \"a\": [\n    null,\n    \"x\"\n  ]\n}"
+        );
+    }
+
+    #[test]
+    fn option_and_conversions() {
+        assert_eq!(Value::from(None::<u32>), Value::Null);
+        assert_eq!(Value::from(Some(7u32)), Value::Integer(7));
+        assert_eq!(Value::from(3usize), Value::Integer(3));
+        assert_eq!(
+            Value::from(String::from("x")),
+            Value::String("x".to_string())
+        );
+        // insert у не объекта ничего не делает
+        let mut value = Value::from(1i64);
+        value.insert("a", 2i64);
+        assert_eq!(value, Value::Integer(1));
+    }
+
+    #[test]
+    fn nested_output() {
+        let mut tags = Value::object();
+        tags.insert("title", "Кино \"в кавычках\"\n\\ c:\\path\t\u{0}\u{7f}");
+        tags.insert("quote\"key\\", "");
+        let mut stream = Value::object();
+        stream.insert("index", 0usize);
+        stream.insert("width", 1920u32);
+        stream.insert("duration", 12.345);
+        stream.insert("start_time", -0.5);
+        stream.insert("bit_rate", None::<i64>);
+        stream.insert("min", i64::MIN);
+        stream.insert("tags", tags);
+        let mut value = Value::object();
+        value.insert("streams", vec![stream, Value::object()]);
+        value.insert("chapters", Vec::<Value>::new());
+
+        let expected = [
+            "{",
+            "  \"streams\": [",
+            "    {",
+            "      \"index\": 0,",
+            "      \"width\": 1920,",
+            "      \"duration\": 12.345,",
+            "      \"start_time\": -0.5,",
+            "      \"bit_rate\": null,",
+            "      \"min\": -9223372036854775808,",
+            "      \"tags\": {",
+            "        \"title\": \"Кино \\\"в кавычках\\\"\\n\\\\ c:\\\\path\\t\\u0000\u{7f}\",",
+            "        \"quote\\\"key\\\\\": \"\"",
+            "      }",
+            "    },",
+            "    {}",
+            "  ],",
+            "  \"chapters\": []",
+            "}",
+        ]
+        .join("\n");
+        assert_eq!(value.to_string(), expected);
+    }
+}
@@ -0,0 +1,157 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use anyhow::{anyhow, Result};
+use ffmpeg::format::{input, Pixel};
+use ffmpeg::media::Type;
+use ffmpeg::util::dictionary::Ref as DictionaryRef;
+use ffmpeg::{rescale, ChannelLayout, Rational};
+use std::env;
+
+mod json;
+
+use json::Value;
+
+// то же что ffmpeg::format::context::input::dump, только в JSON и в stdout
+// probe movie.mkv | jq .streams
+fn main() -> Result<()> {
+    // регистриует все доступные форматы, кодеки и т.д.
+    ffmpeg::init().unwrap();
+
+    let path = env::args()
+        .nth(1)
+        .ok_or_else(|| anyhow!("no input specified"))?;
+    // открываем указанный input, по сути читает header файла и информацию о потоках
+    let ictx = input(&path)?;
+
+    let mut format = Value::object();
+    format.insert("filename", path.as_str());
+    format.insert("name", ictx.format().name());
+    format.insert("long_name", ictx.format().description());
+    // длительность и время начала у контекста всегда в AV_TIME_BASE
+    format.insert("duration", seconds(ictx.duration(), rescale::TIME_BASE));
+    format.insert("bit_rate", Some(ictx.bit_rate()).filter(|&rate| rate > 0));
+    format.insert("tags", tags(ictx.metadata()));
+
+    let chapters = ictx
+        .chapters()
+        .map(|chapter| {
+            let mut value = Value::object();
+            value.insert("id", chapter.id());
+            value.insert("start", seconds(chapter.start(), chapter.time_base()));
+            value.insert("end", seconds(chapter.end(), chapter.time_base()));
+            value.insert("title", chapter.metadata().get("title"));
+            value.insert("tags", tags(chapter.metadata()));
+            value
+        })
+        .collect::<Vec<_>>();
+
+    let streams = ictx
+        .streams()
+        .map(|stream| probe_stream(&stream))
+        .collect::<Vec<_>>();
+
+    let mut root = Value::object();
+    root.insert("format", format);
+    root.insert("chapters", chapters);
+    root.insert("streams", streams);
+    println!("{}", root);
+
+    Ok(())
+}
+
+fn probe_stream(stream: &ffmpeg::format::stream::Stream) -> Value {
+    let codec = stream.codec();
+    let medium = codec.medium();
+
+    let mut value = Value::object();
+    value.insert("index", stream.index());
+    value.insert("type", media_type(medium));
+    value.insert("codec", codec.id().name());
+    value.insert("time_base", rational(stream.time_base()));
+    value.insert("duration", seconds(stream.duration(), stream.time_base()));
+    value.insert("language", stream.metadata().get("language"));
+
+    // остальное знает только открытый декодер, если декодера нет, то и этих полей нет
+    match medium {
+        Type::Video => {
+            if let Ok(decoder) = codec.decoder().video() {
+                value.insert("width", decoder.width());
+                value.insert("height", decoder.height());
+                value.insert("pixel_format", pixel_format(decoder.format()));
+                let rate = stream.avg_frame_rate();
+                value.insert(
+                    "frame_rate",
+                    Some(rate).filter(|rate| rate.numerator() > 0).map(rational),
+                );
+            }
+        }
+        Type::Audio => {
+            if let Ok(decoder) = codec.decoder().audio() {
+                value.insert("sample_format", decoder.format().name());
+                value.insert("sample_rate", decoder.rate());
+                value.insert("channels", u32::from(decoder.channels()));
+                value.insert(
+                    "channel_layout",
+                    channel_layout(decoder.channel_layout(), decoder.channels()),
+                );
+            }
+        }
+        _ => {}
+    }
+
+    value.insert("tags", tags(stream.metadata()));
+    value
+}
+
+fn media_type(medium: Type) -> &'static str {
+    match medium {
+        Type::Video => "video",
+        Type::Audio => "audio",
+        Type::Data => "data",
+        Type::Subtitle => "subtitle",
+        Type::Attachment => "attachment",
+        Type::Unknown => "unknown",
+    }
+}
+
+fn tags(metadata: DictionaryRef) -> Value {
+    let mut value = Value::object();
+    for (key, tag) in metadata.iter() {
+        value.insert(key, tag);
+    }
+    value
+}
+
+// время в секундах, AV_NOPTS_VALUE (неизвестно) превращается в null
+fn seconds(timestamp: i64, time_base: Rational) -> Option<f64> {
+    if timestamp == ffmpeg::ffi::AV_NOPTS_VALUE {
+        None
+    } else {
+        Some(timestamp as f64 * f64::from(time_base))
+    }
+}
+
+// дроби пишем строкой как ffprobe: "1/90000", "30000/1001"
+fn rational(value: Rational) -> String {
+    format!("{}/{}", value.numerator(), value.denominator())
+}
+
+fn pixel_format(format: Pixel) -> Option<&'static str> {
+    format.descriptor().map(|descriptor| descriptor.name())
+}
+
+// строка вида "stereo" или "5.1(side)" от av_get_channel_layout_string()
+fn channel_layout(layout: ChannelLayout, channels: u16) -> String {
+    let mut buffer = [0 as std::os::raw::c_char; 64];
+    unsafe {
+        ffmpeg::ffi::av_get_channel_layout_string(
+            buffer.as_mut_ptr(),
+            buffer.len() as i32,
+            i32::from(channels),
+            layout.bits(),
+        );
+        std::ffi::CStr::from_ptr(buffer.as_ptr())
+            .to_string_lossy()
+            .into_owned()
+    }
+}
@@ -0,0 +1,248 @@
+use ffmpeg::codec::packet::side_data::Type as SideDataType;
+use ffmpeg::format::stream::Stream;
+use image::{imageops, RgbImage};
+use std::convert::TryInto;
+
+// один шаг поворота, названия как у значений фильтра transpose
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Step {
+    // на 90 градусов по часовой
+    Clock,
+    // на 90 градусов против часовой
+    CClock,
+    // по часовой и отразить по вертикали
+    ClockFlip,
+    // против часовой и отразить по вертикали, то есть просто поменять местами x и y
+    CClockFlip,
+    HFlip,
+    VFlip,
+}
+
+impl Step {
+    fn filter(self) -> &'static str {
+        match self {
+            Step::Clock => "transpose=clock",
+            Step::CClock => "transpose=cclock",
+            Step::ClockFlip => "transpose=clock_flip",
+            Step::CClockFlip => "transpose=cclock_flip",
+            Step::HFlip => "hflip",
+            Step::VFlip => "vflip",
+        }
+    }
+
+    fn transposes(self) -> bool {
+        !matches!(self, Step::HFlip | Step::VFlip)
+    }
+}
+
+// как повернуть кадры потока, чтобы они смотрели как надо
+// телефоны пишут видео как есть с матрицы, а как его держали, записывают в метаданные:
+// матрицей отображения в side data потока или, в старых файлах, тегом rotate
+// решение какие фильтры ставить такое же как у ffmpeg с -autorotate
+#[derive(Clone, Default, Debug)]
+pub struct Rotation {
+    steps: Vec<Step>,
+}
+
+impl Rotation {
+    pub fn of(stream: &Stream) -> Rotation {
+        let matrix = stream
+            .side_data()
+            .find(|side_data| side_data.kind() == SideDataType::DisplayMatrix)
+            .and_then(|side_data| display_matrix(side_data.data()));
+        let rotate = stream
+            .metadata()
+            .get("rotate")
+            .and_then(|tag| tag.parse().ok());
+        Rotation::from_metadata(matrix, rotate)
+    }
+
+    // тег rotate (градусы по часовой) смотрим, только если матрицы нет
+    fn from_metadata(matrix: Option<[i32; 9]>, rotate: Option<f64>) -> Rotation {
+        let theta = match &matrix {
+            // av_display_rotation_get() считает против часовой, а нам нужно по часовой
+            Some(matrix) => -unsafe { ffmpeg::ffi::av_display_rotation_get(matrix.as_ptr()) },
+            None => match rotate {
+                Some(degrees) => degrees,
+                None => return Rotation::default(),
+            },
+        };
+        if theta.is_nan() {
+            return Rotation::default();
+        }
+        // приводим к [0, 360), почти 360 считаем нулём
+        let theta = theta - 360.0 * (theta / 360.0 + 0.9 / 360.0).floor();
+        let near = |degrees: f64| (theta - degrees).abs() < 1.0;
+        // коэффициенты матрицы 16.16, по их знакам видно есть ли ещё и отражение
+        let flipped = |index: usize| matrix.map_or(false, |matrix| matrix[index] < 0);
+
+        let mut steps = Vec::new();
+        if near(90.0) {
+            steps.push(if matrix.map_or(false, |matrix| matrix[3] > 0) {
+                Step::CClockFlip
+            } else {
+                Step::Clock
+            });
+        } else if near(180.0) {
+            if matrix.is_none() || flipped(0) {
+                steps.push(Step::HFlip);
+            }
+            if matrix.is_none() || flipped(4) {
+                steps.push(Step::VFlip);
+            }
+        } else if near(270.0) {
+            steps.push(if flipped(3) {
+                Step::ClockFlip
+            } else {
+                Step::CClock
+            });
+        } else if near(0.0) {
+            if flipped(4) {
+                steps.push(Step::VFlip);
+            }
+        }
+        // повороты не на прямой угол встречаются только в собранных руками файлах, их не трогаем
+        Rotation { steps }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    // размер кадра после поворота
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        if self.steps.iter().any(|step| step.transposes()) {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    // цепочка фильтров для графа, None если поворачивать не надо
+    pub fn filter(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let filters = self
+            .steps
+            .iter()
+            .map(|step| step.filter())
+            .collect::<Vec<_>>();
+        Some(filters.join(","))
+    }
+
+    // поворачивает уже готовую RGB картинку, для сохранения кадров в файлы
+    pub fn apply(&self, image: RgbImage) -> RgbImage {
+        self.steps.iter().fold(image, |image, step| match step {
+            Step::Clock => imageops::rotate90(&image),
+            Step::CClock => imageops::rotate270(&image),
+            Step::ClockFlip => imageops::flip_vertical(&imageops::rotate90(&image)),
+            Step::CClockFlip => imageops::flip_vertical(&imageops::rotate270(&image)),
+            Step::HFlip => imageops::flip_horizontal(&image),
+            Step::VFlip => imageops::flip_vertical(&image),
+        })
+    }
+}
+
+// матрица 3x3 из side data, числа в порядке байт машины
+fn display_matrix(data: &[u8]) -> Option<[i32; 9]> {
+    if data.len() < 36 {
+        return None;
+    }
+    let mut matrix = [0; 9];
+    for (value, bytes) in matrix.iter_mut().zip(data.chunks_exact(4)) {
+        *value = i32::from_ne_bytes(bytes.try_into().unwrap());
+    }
+    Some(matrix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // матрица как её пишут контейнеры: поворот по часовой на degrees и отражения
+    fn matrix(degrees: f64, hflip: bool, vflip: bool) -> [i32; 9] {
+        let mut matrix = [0; 9];
+        unsafe {
+            ffmpeg::ffi::av_display_rotation_set(matrix.as_mut_ptr(), degrees);
+            ffmpeg::ffi::av_display_matrix_flip(matrix.as_mut_ptr(), hflip as i32, vflip as i32);
+        }
+        matrix
+    }
+
+    #[test]
+    fn display_matrix_steps() {
+        use Step::*;
+        let table: &[(f64, bool, bool, &[Step])] = &[
+            (0.0, false, false, &[]),
+            (90.0, false, false, &[Clock]),
+            (180.0, false, false, &[HFlip, VFlip]),
+            (270.0, false, false, &[CClock]),
+            (-90.0, false, false, &[CClock]),
+            (-180.0, false, false, &[HFlip, VFlip]),
+            (-270.0, false, false, &[Clock]),
+            (360.0, false, false, &[]),
+            // отражённые
+            (0.0, false, true, &[VFlip]),
+            (0.0, true, false, &[HFlip]),
+            (90.0, true, false, &[CClockFlip]),
+            (270.0, true, false, &[ClockFlip]),
+            (180.0, true, false, &[VFlip]),
+            (180.0, false, true, &[HFlip]),
+            // не прямой угол не трогаем
+            (45.0, false, false, &[]),
+        ];
+        for &(degrees, hflip, vflip, steps) in table {
+            let rotation = Rotation::from_metadata(Some(matrix(degrees, hflip, vflip)), None);
+            assert_eq!(
+                rotation.steps, steps,
+                "{} degrees, hflip {}, vflip {}",
+                degrees, hflip, vflip
+            );
+        }
+    }
+
+    #[test]
+    fn rotate_tag_steps() {
+        use Step::*;
+        let table: &[(f64, &[Step])] = &[
+            (0.0, &[]),
+            (90.0, &[Clock]),
+            (180.0, &[HFlip, VFlip]),
+            (270.0, &[CClock]),
+            (-90.0, &[CClock]),
+            (-270.0, &[Clock]),
+            (450.0, &[Clock]),
+            // почти 360 это ноль
+            (359.5, &[]),
+            (30.0, &[]),
+            (f64::NAN, &[]),
+        ];
+        for &(degrees, steps) in table {
+            let rotation = Rotation::from_metadata(None, Some(degrees));
+            assert_eq!(rotation.steps, steps, "rotate {}", degrees);
+        }
+        assert!(Rotation::from_metadata(None, None).is_empty());
+        // матрица важнее тега
+        let rotation = Rotation::from_metadata(Some(matrix(0.0, false, false)), Some(90.0));
+        assert!(rotation.is_empty());
+    }
+
+    #[test]
+    fn filters_and_sizes() {
+        let table: &[(f64, Option<&str>, (u32, u32))] = &[
+            (0.0, None, (1920, 1080)),
+            (90.0, Some("transpose=clock"), (1080, 1920)),
+            (180.0, Some("hflip,vflip"), (1920, 1080)),
+            (270.0, Some("transpose=cclock"), (1080, 1920)),
+        ];
+        for &(degrees, filter, size) in table {
+            let rotation = Rotation::from_metadata(None, Some(degrees));
+            assert_eq!(rotation.filter().as_deref(), filter, "rotate {}", degrees);
+            assert_eq!(rotation.size(1920, 1080), size, "rotate {}", degrees);
+        }
+        let flipped = Rotation::from_metadata(Some(matrix(90.0, true, false)), None);
+        assert_eq!(flipped.filter().as_deref(), Some("transpose=cclock_flip"));
+        assert_eq!(flipped.size(1920, 1080), (1080, 1920));
+    }
+}
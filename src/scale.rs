@@ -0,0 +1,448 @@
+use anyhow::{anyhow, Result};
+use ffmpeg::format::Pixel;
+use ffmpeg::software::scaling::{context::Context, flag::Flags};
+use ffmpeg::util::color::{Primaries, Range, Space};
+use ffmpeg::util::frame::video::Video;
+use std::os::raw::c_int;
+use std::ptr;
+
+// алгоритмы swscale, которые можно выбрать через --scaler, имена как у ffmpeg -sws_flags
+const SCALERS: &[(&str, Flags)] = &[
+    ("fast_bilinear", Flags::FAST_BILINEAR),
+    ("bilinear", Flags::BILINEAR),
+    ("bicubic", Flags::BICUBIC),
+    ("lanczos", Flags::LANCZOS),
+    ("spline", Flags::SPLINE),
+];
+
+// как переводить кадры в формат для показа или сохранения: каким алгоритмом и в какой размер
+// по умолчанию билинейный и размер исходного кадра, как было раньше везде
+#[derive(Clone, Copy)]
+pub struct Scaling {
+    name: &'static str,
+    flags: Flags,
+    // None значит размер исходного кадра, 0 по одной из сторон значит сохранить пропорции
+    size: Option<(u32, u32)>,
+}
+
+impl Default for Scaling {
+    fn default() -> Scaling {
+        Scaling {
+            name: "bilinear",
+            flags: Flags::BILINEAR,
+            size: None,
+        }
+    }
+}
+
+impl Scaling {
+    pub fn new() -> Scaling {
+        Scaling::default()
+    }
+
+    // применяет одну опцию командной строки, для бинарников со своим разбором аргументов
+    pub fn option(mut self, name: &str, value: &str) -> Result<Scaling> {
+        match name {
+            "--scale" => {
+                let mut sides = value.splitn(2, 'x').map(|side| side.parse::<u32>().ok());
+                match (sides.next().flatten(), sides.next().flatten()) {
+                    (Some(width), Some(height)) if width > 0 || height > 0 => {
+                        self.size = Some((width, height));
+                        Ok(self)
+                    }
+                    _ => Err(anyhow!(
+                        "scale must be WxH, for example 1280x720 or 640x0, not {}",
+                        value
+                    )),
+                }
+            }
+            "--scaler" => {
+                let &(name, flags) =
+                    SCALERS
+                        .iter()
+                        .find(|(name, _)| *name == value)
+                        .ok_or_else(|| {
+                            let names = SCALERS.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+                            anyhow!("scaler must be one of {}, not {}", names.join(", "), value)
+                        })?;
+                self.name = name;
+                self.flags = flags;
+                Ok(self)
+            }
+            _ => Err(anyhow!("unknown scaling option {}", name)),
+        }
+    }
+
+    // имя алгоритма для фильтра scale в графе фильтров
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    // задан ли размер через --scale
+    pub fn is_sized(&self) -> bool {
+        self.size.is_some()
+    }
+
+    // размер кадра width x height после перевода
+    // стороны чётные, иначе у YUV420P цветность не делится на два без остатка,
+    // поэтому и без --scale от нечётной стороны отрезается столбец или строка
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        match self.size {
+            None => (even(width), even(height)),
+            Some((0, to_height)) => (even(width * to_height / height.max(1)), even(to_height)),
+            Some((to_width, 0)) => (even(to_width), even(height * to_width / width.max(1))),
+            Some((to_width, to_height)) => (even(to_width), even(to_height)),
+        }
+    }
+
+    // то же что size(), но если картинка не влезает в bounds, то уменьшаем её с сохранением пропорций
+    // увеличивать до bounds не будем, это дешевле сделать при отрисовке
+    pub fn fit(self, width: u32, height: u32, bounds: (u32, u32)) -> Scaling {
+        let (width, height) = self.size(width, height);
+        let ratio = f64::min(
+            f64::from(bounds.0) / f64::from(width.max(1)),
+            f64::from(bounds.1) / f64::from(height.max(1)),
+        );
+        if ratio >= 1.0 {
+            return Scaling {
+                size: Some((width, height)),
+                ..self
+            };
+        }
+        Scaling {
+            size: Some((
+                even((f64::from(width) * ratio) as u32),
+                even((f64::from(height) * ratio) as u32),
+            )),
+            ..self
+        }
+    }
+
+    // фиксирует размер из size(), после этого и context() переводит в чётный размер,
+    // как нужно энкодерам YUV420P: libx264 кадры нечётного размера не берёт
+    pub fn even_size(self, width: u32, height: u32) -> Scaling {
+        Scaling {
+            size: Some(self.size(width, height)),
+            ..self
+        }
+    }
+
+    // фильтр scale для графа фильтров, если размер задан через --scale, иначе None
+    // размер считает сам граф по кадрам, которые до него доходят, -2 сохраняет пропорции
+    // и делает сторону чётной, как size()
+    pub fn filter(&self) -> Option<String> {
+        let side = |side: u32| match side {
+            0 => "-2".to_string(),
+            side => even(side).to_string(),
+        };
+        self.size.map(|(width, height)| {
+            format!("scale={}:{}:flags={}", side(width), side(height), self.name)
+        })
+    }
+
+    // swscale из кадров format width x height в кадры to нужного размера
+    pub fn context(
+        &self,
+        format: Pixel,
+        width: u32,
+        height: u32,
+        to: Pixel,
+    ) -> Result<Scaler, ffmpeg::Error> {
+        // без --scale кадр переводится как есть: скриншотам в RGB чётные стороны не нужны,
+        // а кому нужны, фиксируют размер через fit() или even_size()
+        let (to_width, to_height) = match self.size {
+            None => (width, height),
+            Some(_) => self.size(width, height),
+        };
+        Ok(Scaler {
+            context: Context::get(format, width, height, to, to_width, to_height, self.flags)?,
+            to,
+            colors: None,
+        })
+    }
+}
+
+// цвета кадра с точки зрения swscale: какой матрицей YUV получен из RGB и какой у него диапазон
+#[derive(Clone, Copy, PartialEq)]
+struct Colors {
+    space: Space,
+    range: Range,
+}
+
+impl Colors {
+    fn of(frame: &Video) -> Colors {
+        let space = match frame.color_space() {
+            Space::Unspecified | Space::Reserved => guess_space(frame),
+            space => space,
+        };
+        Colors {
+            space,
+            range: frame.color_range(),
+        }
+    }
+}
+
+// многие файлы матрицу не указывают, тогда угадываем как плееры:
+// по основным цветам, а если и их нет, то по размеру кадра, HD почти всегда BT.709
+fn guess_space(frame: &Video) -> Space {
+    match frame.color_primaries() {
+        Primaries::BT709 => Space::BT709,
+        Primaries::BT2020 => Space::BT2020NCL,
+        Primaries::BT470BG | Primaries::SMPTE170M => Space::BT470BG,
+        _ if frame.height() >= 720 => Space::BT709,
+        _ => Space::BT470BG,
+    }
+}
+
+// контекст swscale, который сам настраивает коэффициенты под цвета входных кадров
+// без этого swscale считает любое видео BT.601 с ограниченным диапазоном:
+// у HD видео в BT.709 едут оттенки, а у JPEG с полным диапазоном пропадает контраст
+// основные цвета swscale не переводит, BT.2020 так и останется в своей гамме
+pub struct Scaler {
+    context: Context,
+    to: Pixel,
+    // цвета, под которые контекст сейчас настроен
+    colors: Option<Colors>,
+}
+
+impl Scaler {
+    pub fn run(&mut self, input: &Video, output: &mut Video) -> Result<(), ffmpeg::Error> {
+        let colors = Colors::of(input);
+        if self.colors != Some(colors) {
+            self.configure(colors);
+            self.colors = Some(colors);
+        }
+        self.context.run(input, output)?;
+
+        // YUV на выходе всегда BT.601 с ограниченным диапазоном, плееры ставят SDL
+        // SDL_YUV_CONVERSION_MODE=BT601, иначе у текстур выше 576 строк SDL берёт BT.709,
+        // а RGB всегда с полным, помечаем это в кадре, чтобы его можно было перевести ещё раз
+        if is_rgb(self.to) {
+            output.set_color_space(Space::RGB);
+            output.set_color_range(Range::JPEG);
+        } else {
+            output.set_color_space(Space::BT470BG);
+            output.set_color_range(Range::MPEG);
+        }
+        output.set_color_primaries(input.color_primaries());
+        Ok(())
+    }
+
+    fn configure(&mut self, colors: Colors) {
+        unsafe {
+            let context = self.context.as_mut_ptr();
+            // яркость, контраст и насыщенность оставляем как есть
+            let mut inv_table = ptr::null_mut();
+            let mut src_range = 0;
+            let mut table = ptr::null_mut();
+            let mut dst_range = 0;
+            let mut brightness = 0;
+            let mut contrast = 0;
+            let mut saturation = 0;
+            ffmpeg::ffi::sws_getColorspaceDetails(
+                context,
+                &mut inv_table,
+                &mut src_range,
+                &mut table,
+                &mut dst_range,
+                &mut brightness,
+                &mut contrast,
+                &mut saturation,
+            );
+
+            // если диапазон не указан, остаётся тот, что swscale вывел из формата, у YUVJ он полный
+            let src_range = match colors.range {
+                Range::JPEG => 1,
+                Range::MPEG => 0,
+                _ => src_range,
+            };
+            let dst_range = if is_rgb(self.to) { 1 } else { 0 };
+            // значения AVColorSpace совпадают с SWS_CS_*, неизвестные дают BT.601
+            let space: ffmpeg::ffi::AVColorSpace = colors.space.into();
+            ffmpeg::ffi::sws_setColorspaceDetails(
+                context,
+                ffmpeg::ffi::sws_getCoefficients(space as c_int),
+                src_range,
+                ffmpeg::ffi::sws_getCoefficients(ffmpeg::ffi::SWS_CS_ITU601 as c_int),
+                dst_range,
+                brightness,
+                contrast,
+                saturation,
+            );
+        }
+    }
+}
+
+fn is_rgb(format: Pixel) -> bool {
+    unsafe {
+        let descriptor = ffmpeg::ffi::av_pix_fmt_desc_get(format.into());
+        !descriptor.is_null()
+            && (*descriptor).flags & u64::from(ffmpeg::ffi::AV_PIX_FMT_FLAG_RGB) != 0
+    }
+}
+
+fn even(size: u32) -> u32 {
+    (size & !1).max(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: u32 = 16;
+    // swscale считает таблицами и в целых, так что пара единиц расхождения это норма,
+    // а перепутанная матрица или диапазон дают ошибку в десятки
+    const TOLERANCE: i32 = 4;
+
+    // 75% цветные полосы: белая, жёлтая, голубая, зелёная, пурпурная, красная, синяя, чёрная
+    const BARS: [[u8; 3]; 8] = [
+        [191, 191, 191],
+        [191, 191, 0],
+        [0, 191, 191],
+        [0, 191, 0],
+        [191, 0, 191],
+        [191, 0, 0],
+        [0, 0, 191],
+        [0, 0, 0],
+    ];
+
+    // коэффициенты яркости красного и синего
+    const BT601: (f64, f64) = (0.299, 0.114);
+    const BT709: (f64, f64) = (0.2126, 0.0722);
+
+    // YUV полосы так, как его записал бы энкодер с этой матрицей и диапазоном
+    fn yuv(rgb: [u8; 3], (kr, kb): (f64, f64), range: Range) -> [u8; 3] {
+        let channel = |index: usize| f64::from(rgb[index]) / 255.0;
+        let (r, g, b) = (channel(0), channel(1), channel(2));
+        let y = kr * r + (1.0 - kr - kb) * g + kb * b;
+        let u = (b - y) / (2.0 * (1.0 - kb));
+        let v = (r - y) / (2.0 * (1.0 - kr));
+        let (y, u, v) = match range {
+            Range::JPEG => (255.0 * y, 128.0 + 255.0 * u, 128.0 + 255.0 * v),
+            _ => (16.0 + 219.0 * y, 128.0 + 224.0 * u, 128.0 + 224.0 * v),
+        };
+        let round = |value: f64| value.round().max(0.0).min(255.0) as u8;
+        [round(y), round(u), round(v)]
+    }
+
+    // кадр YUV420P одного цвета, чтобы на результат не влияла интерполяция цветности
+    fn solid(yuv: [u8; 3], space: Space, range: Range) -> Video {
+        let mut frame = Video::new(Pixel::YUV420P, SIZE, SIZE);
+        for (plane, value) in yuv.iter().enumerate() {
+            for byte in frame.data_mut(plane) {
+                *byte = *value;
+            }
+        }
+        frame.set_color_space(space);
+        frame.set_color_range(range);
+        frame
+    }
+
+    // пиксель из середины кадра RGB24
+    fn center(frame: &Video) -> [u8; 3] {
+        let offset = (SIZE / 2) as usize * frame.stride(0) + (SIZE / 2) as usize * 3;
+        let data = frame.data(0);
+        [data[offset], data[offset + 1], data[offset + 2]]
+    }
+
+    fn check_bars(space: Space, matrix: (f64, f64), range: Range) {
+        let mut scaler = Scaling::new()
+            .context(Pixel::YUV420P, SIZE, SIZE, Pixel::RGB24)
+            .unwrap();
+        for &bar in BARS.iter() {
+            let input = solid(yuv(bar, matrix, range), space, range);
+            let mut output = Video::empty();
+            scaler.run(&input, &mut output).unwrap();
+            let rgb = center(&output);
+            for channel in 0..3 {
+                assert!(
+                    (i32::from(rgb[channel]) - i32::from(bar[channel])).abs() <= TOLERANCE,
+                    "{:?} {:?}: expected {:?}, got {:?}",
+                    space,
+                    range,
+                    bar,
+                    rgb
+                );
+            }
+            assert_eq!(output.color_space(), Space::RGB);
+            assert_eq!(output.color_range(), Range::JPEG);
+        }
+    }
+
+    #[test]
+    fn bt601_limited_bars() {
+        check_bars(Space::BT470BG, BT601, Range::MPEG);
+    }
+
+    #[test]
+    fn bt601_full_bars() {
+        check_bars(Space::BT470BG, BT601, Range::JPEG);
+    }
+
+    #[test]
+    fn bt709_limited_bars() {
+        check_bars(Space::BT709, BT709, Range::MPEG);
+    }
+
+    #[test]
+    fn bt709_full_bars() {
+        check_bars(Space::BT709, BT709, Range::JPEG);
+    }
+
+    // у маленького кадра без матрицы и основных цветов угадывается BT.601
+    #[test]
+    fn unspecified_small_frame_is_bt601() {
+        check_bars(Space::Unspecified, BT601, Range::MPEG);
+    }
+
+    // один и тот же scaler перенастраивается, когда у кадров меняются цвета
+    #[test]
+    fn scaler_follows_color_changes() {
+        let mut scaler = Scaling::new()
+            .context(Pixel::YUV420P, SIZE, SIZE, Pixel::RGB24)
+            .unwrap();
+        let green = BARS[3];
+        for &(space, matrix, range) in &[
+            (Space::BT709, BT709, Range::MPEG),
+            (Space::BT470BG, BT601, Range::JPEG),
+            (Space::BT709, BT709, Range::JPEG),
+        ] {
+            let input = solid(yuv(green, matrix, range), space, range);
+            let mut output = Video::empty();
+            scaler.run(&input, &mut output).unwrap();
+            let rgb = center(&output);
+            for channel in 0..3 {
+                assert!((i32::from(rgb[channel]) - i32::from(green[channel])).abs() <= TOLERANCE);
+            }
+        }
+    }
+
+    #[test]
+    fn sizes_are_even_and_keep_aspect() {
+        let scaling = Scaling::new().option("--scale", "640x0").unwrap();
+        assert_eq!(scaling.size(1920, 1080), (640, 360));
+        let scaling = Scaling::new().option("--scale", "0x101").unwrap();
+        assert_eq!(scaling.size(1920, 1080), (178, 100));
+        assert_eq!(Scaling::new().size(853, 481), (852, 480));
+        assert_eq!(
+            Scaling::new()
+                .fit(853, 481, (u32::MAX, u32::MAX))
+                .size(853, 481),
+            (852, 480)
+        );
+        assert_eq!(
+            Scaling::new().even_size(853, 481).size(853, 481),
+            (852, 480)
+        );
+    }
+
+    #[test]
+    fn filter_keeps_aspect_with_minus_two() {
+        assert_eq!(Scaling::new().filter(), None);
+        let scaling = Scaling::new().option("--scale", "641x0").unwrap();
+        assert_eq!(
+            scaling.filter().as_deref(),
+            Some("scale=640:-2:flags=bilinear")
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use crate::rotation::Rotation;
+use crate::scale::Scaling;
+use anyhow::{Context as ErrorContext, Result};
+use ffmpeg::format::Pixel;
+use ffmpeg::util::frame::video::Video;
+use image::RgbImage;
+use std::path::{Path, PathBuf};
+
+// сохраняет RGB24 кадр в файл, формат картинки выбирается по расширению
+pub fn save_frame<P: AsRef<Path>>(frame: &Video, path: P) -> Result<()> {
+    save_rotated_frame(frame, &Rotation::default(), path)
+}
+
+// то же, но сначала поворачивает кадр так, как его надо показывать
+pub fn save_rotated_frame<P: AsRef<Path>>(
+    frame: &Video,
+    rotation: &Rotation,
+    path: P,
+) -> Result<()> {
+    rotation
+        .apply(frame_image(frame))
+        .save(path)
+        .context("couldn't save frame")
+}
+
+// RGB24 кадр как картинка для image
+pub fn frame_image(frame: &Video) -> RgbImage {
+    // строки кадра выровнены и могут быть длиннее самой картинки, поэтому хвосты отрезаем
+    let width = frame.width() as usize * 3;
+    let mut buffer = Vec::with_capacity(width * frame.height() as usize);
+    for line in frame
+        .data(0)
+        .chunks(frame.stride(0))
+        .take(frame.height() as usize)
+    {
+        buffer.extend_from_slice(&line[..width]);
+    }
+    RgbImage::from_raw(frame.width(), frame.height(), buffer).unwrap()
+}
+
+// второй кадр с теми же данными: буферы декодера со счётчиком ссылок не копируются,
+// так что держать исходный кадр для скриншота почти ничего не стоит
+pub fn frame_ref(frame: &Video) -> Result<Video, ffmpeg::Error> {
+    let mut copy = Video::empty();
+    match unsafe { ffmpeg::ffi::av_frame_ref(copy.as_mut_ptr(), frame.as_ptr()) } {
+        0 => Ok(copy),
+        e => Err(ffmpeg::Error::from(e)),
+    }
+}
+
+// сохраняет кадр в любом формате как PNG в текущую папку
+// имя собирается из имени исходного файла и времени кадра: movie_12.345.png
+pub fn save_screenshot(frame: &Video, input: &str, seconds: f64) -> Result<PathBuf> {
+    save_rotated_screenshot(frame, &Rotation::default(), input, seconds)
+}
+
+// то же, но сначала поворачивает кадр так, как его показывает плеер
+pub fn save_rotated_screenshot(
+    frame: &Video,
+    rotation: &Rotation,
+    input: &str,
+    seconds: f64,
+) -> Result<PathBuf> {
+    let mut rgb_frame = Video::empty();
+    Scaling::new()
+        .context(frame.format(), frame.width(), frame.height(), Pixel::RGB24)?
+        .run(frame, &mut rgb_frame)?;
+
+    let name = Path::new(input)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "screenshot".to_string());
+    let path = PathBuf::from(format!("{}_{:.3}.png", name, seconds));
+
+    save_rotated_frame(&rgb_frame, rotation, &path)?;
+    Ok(path)
+}
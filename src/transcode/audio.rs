@@ -0,0 +1,197 @@
+use crate::fifo::SampleFifo;
+use crate::options::Options;
+use crate::write_packets;
+use anyhow::{anyhow, Context, Result};
+use ffmpeg::codec::packet::Packet;
+use ffmpeg::format::stream::Stream;
+use ffmpeg::software::resampling::context::Context as Resampler;
+use ffmpeg::util::frame::audio::Audio;
+use ffmpeg::{codec, decoder, encoder, format, Dictionary, Rational, Rescale};
+use fftut::decode::DecodeErrors;
+
+// аудио поток: декодер -> swresample -> очередь сэмплов -> энкодер
+pub struct AudioTranscoder {
+    pub input_index: usize,
+    output_index: usize,
+    input_time_base: Rational,
+    decoder: decoder::Audio,
+    resampler: Resampler,
+    fifo: SampleFifo,
+    encoder: encoder::audio::Encoder,
+    // сколько сэмплов на канал энкодер ждёт в каждом кадре, 0 если ему всё равно
+    frame_size: usize,
+    // энкодер принимает последний кадр короче frame_size (AV_CODEC_CAP_SMALL_LAST_FRAME)
+    small_last_frame: bool,
+    // время следующего кадра для энкодера в сэмплах, считается от первого декодированного кадра
+    next_pts: Option<i64>,
+    errors: DecodeErrors,
+}
+
+impl AudioTranscoder {
+    // открывает декодер входного потока и добавляет в выходной файл поток с энкодером
+    pub fn new(
+        stream: &Stream,
+        octx: &mut format::context::Output,
+        options: &Options,
+    ) -> Result<AudioTranscoder> {
+        let decoder = options.decoder.audio(stream)?;
+
+        let codec = options.audio_codec.find()?;
+        let audio_codec = codec.audio()?;
+        let global_header = octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
+        let mut output_stream = octx.add_stream(codec)?;
+        let output_index = output_stream.index();
+        let mut encoder = output_stream.codec().encoder().audio()?;
+
+        // формат, частота и каналы берутся из того, что энкодер умеет, как можно ближе к исходным
+        let channel_layout = audio_codec
+            .channel_layouts()
+            .map(|layouts| layouts.best(decoder.channel_layout().channels()))
+            .unwrap_or(ffmpeg::ChannelLayout::STEREO);
+        let rate = match audio_codec.rates() {
+            Some(rates) => {
+                let rates = rates.collect::<Vec<_>>();
+                if rates.contains(&(decoder.rate() as i32)) {
+                    decoder.rate() as i32
+                } else {
+                    // у Opus только 8-48 кГц, 44.1 кГц у него нет
+                    rates.into_iter().max().unwrap_or(48000)
+                }
+            }
+            None => decoder.rate() as i32,
+        };
+        let sample_format = audio_codec
+            .formats()
+            .and_then(|mut formats| formats.next())
+            .ok_or_else(|| anyhow!("{} supports no sample formats", options.audio_codec))?;
+
+        encoder.set_rate(rate);
+        encoder.set_channel_layout(channel_layout);
+        encoder.set_channels(channel_layout.channels());
+        encoder.set_format(sample_format);
+        encoder.set_time_base((1, rate));
+        output_stream.set_time_base((1, rate));
+        if let Some(bit_rate) = options.audio_bit_rate {
+            encoder.set_bit_rate(bit_rate);
+        }
+        if global_header {
+            encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+
+        // встроенный энкодер opus пока экспериментальный и без этого не открывается
+        let mut settings = Dictionary::new();
+        if codec.name() == "opus" {
+            settings.set("strict", "experimental");
+        }
+        let encoder = encoder
+            .open_as_with(codec, settings)
+            .with_context(|| format!("couldn't open audio encoder {}", options.audio_codec))?;
+        output_stream.set_parameters(&encoder);
+
+        let resampler = Resampler::get(
+            decoder.format(),
+            decoder.channel_layout(),
+            decoder.rate(),
+            sample_format,
+            channel_layout,
+            rate as u32,
+        )?;
+
+        Ok(AudioTranscoder {
+            input_index: stream.index(),
+            output_index,
+            input_time_base: stream.time_base(),
+            decoder,
+            resampler,
+            fifo: SampleFifo::new(sample_format, channel_layout),
+            frame_size: encoder.frame_size() as usize,
+            small_last_frame: codec
+                .capabilities()
+                .contains(codec::Capabilities::SMALL_LAST_FRAME),
+            encoder,
+            next_pts: None,
+            errors: DecodeErrors::new(options.error_policy),
+        })
+    }
+
+    pub fn send_packet(
+        &mut self,
+        packet: &Packet,
+        octx: &mut format::context::Output,
+    ) -> Result<()> {
+        if let Some(error) = self.errors.send_packet(&mut self.decoder, packet)? {
+            eprintln!("corrupt audio packet skipped: {}", error);
+        }
+        self.receive_frames(octx)
+    }
+
+    // забирает хвосты из декодера, ресемплера и очереди и опустошает энкодер
+    pub fn finish(&mut self, octx: &mut format::context::Output) -> Result<()> {
+        self.decoder.send_eof()?;
+        self.receive_frames(octx)?;
+
+        let mut resampled = self.resampled_frame(0);
+        self.resampler.flush(&mut resampled)?;
+        self.fifo.push(&resampled);
+        self.encode(octx, true)?;
+
+        self.encoder.send_eof()?;
+        let time_base = self.time_base();
+        write_packets(&mut self.encoder, time_base, self.output_index, octx)
+    }
+
+    fn receive_frames(&mut self, octx: &mut format::context::Output) -> Result<()> {
+        let mut decoded = Audio::empty();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            if self.next_pts.is_none() {
+                let start = decoded.timestamp().unwrap_or(0);
+                self.next_pts = Some(start.rescale(self.input_time_base, self.time_base()));
+            }
+
+            let mut resampled = self.resampled_frame(decoded.samples());
+            self.resampler.run(&decoded, &mut resampled)?;
+            self.fifo.push(&resampled);
+            self.encode(octx, false)?;
+        }
+        Ok(())
+    }
+
+    // нарезает очередь на кадры размера frame_size и отдаёт их энкодеру
+    fn encode(&mut self, octx: &mut format::context::Output, last: bool) -> Result<()> {
+        while let Some(mut frame) =
+            self.fifo
+                .next_frame(self.frame_size, last, self.small_last_frame)
+        {
+            let samples = frame.samples();
+            let pts = self.next_pts.unwrap_or(0);
+            frame.set_pts(Some(pts));
+            self.next_pts = Some(pts + samples as i64);
+            self.encoder
+                .send_frame(&frame)
+                .map_err(|e| anyhow!("couldn't encode audio frame: {}", e))?;
+            let time_base = self.time_base();
+            write_packets(&mut self.encoder, time_base, self.output_index, octx)?;
+        }
+        Ok(())
+    }
+
+    // пустой кадр под всё, что ресемплер отдаст из samples новых сэмплов и своего буфера
+    // если отдать ему Audio::empty(), то он выделит кадр по числу входных сэмплов,
+    // и при повышении частоты остаток будет копиться у него внутри
+    fn resampled_frame(&mut self, samples: usize) -> Audio {
+        let capacity = unsafe {
+            ffmpeg::ffi::swr_get_out_samples(self.resampler.as_mut_ptr(), samples as i32)
+        };
+        let output = self.resampler.output();
+        Audio::new(
+            output.format,
+            capacity.max(1) as usize,
+            output.channel_layout,
+        )
+    }
+
+    // у энкодера время в сэмплах
+    fn time_base(&self) -> Rational {
+        Rational::new(1, self.encoder.rate() as i32)
+    }
+}
@@ -0,0 +1,198 @@
+use ffmpeg::format::Sample;
+use ffmpeg::util::frame::audio::Audio;
+use ffmpeg::ChannelLayout;
+use std::collections::VecDeque;
+
+// очередь сэмплов между ресемплером и аудио энкодером
+// декодер отдаёт кадры любого размера, а AAC хочет ровно по 1024 сэмпла, Opus по 960,
+// поэтому сэмплы копятся здесь и нарезаются кадрами нужного энкодеру размера
+pub struct SampleFifo {
+    format: Sample,
+    channel_layout: ChannelLayout,
+    // у planar форматов своя очередь байт на каждый канал, у packed одна на все
+    planes: Vec<VecDeque<u8>>,
+    // сколько байт занимает один сэмпл в одной плоскости
+    sample_bytes: usize,
+}
+
+impl SampleFifo {
+    pub fn new(format: Sample, channel_layout: ChannelLayout) -> SampleFifo {
+        let channels = channel_layout.channels() as usize;
+        let (planes, sample_bytes) = if format.is_planar() {
+            (channels, format.bytes())
+        } else {
+            (1, format.bytes() * channels)
+        };
+        SampleFifo {
+            format,
+            channel_layout,
+            planes: vec![VecDeque::new(); planes],
+            sample_bytes,
+        }
+    }
+
+    // сколько сэмплов на канал лежит в очереди
+    pub fn samples(&self) -> usize {
+        self.planes[0].len() / self.sample_bytes
+    }
+
+    // кадр должен быть в формате очереди, то есть уже после ресемплера
+    pub fn push(&mut self, frame: &Audio) {
+        let len = frame.samples() * self.sample_bytes;
+        for (index, plane) in self.planes.iter_mut().enumerate() {
+            plane.extend(&frame.data(index)[..len]);
+        }
+    }
+
+    // добивает очередь тишиной до samples сэмплов на канал
+    // у беззнаковых 8-битных сэмплов тишина это середина, 0x80, у остальных форматов нули
+    pub fn pad(&mut self, samples: usize) {
+        let silence = match self.format {
+            Sample::U8(_) => 0x80,
+            _ => 0,
+        };
+        let len = samples * self.sample_bytes;
+        for plane in &mut self.planes {
+            if plane.len() < len {
+                plane.resize(len, silence);
+            }
+        }
+    }
+
+    // достаёт кадр из samples сэмплов, если их в очереди меньше, то сколько есть
+    pub fn pop(&mut self, samples: usize) -> Audio {
+        let samples = samples.min(self.samples());
+        let mut frame = Audio::new(self.format, samples, self.channel_layout);
+        let len = samples * self.sample_bytes;
+        for (index, plane) in self.planes.iter_mut().enumerate() {
+            for (to, from) in frame.data_mut(index)[..len]
+                .iter_mut()
+                .zip(plane.drain(..len))
+            {
+                *to = from;
+            }
+        }
+        frame
+    }
+
+    // следующий кадр для энкодера с кадрами по frame_size сэмплов, 0 значит любого размера
+    // последний кадр в конце файла (last) короче, его берут только энкодеры
+    // с SMALL_LAST_FRAME, а остальным (ac3, например) он добивается тишиной до frame_size
+    // None если очередь пуста или для целого кадра сэмплов пока не хватает
+    pub fn next_frame(
+        &mut self,
+        frame_size: usize,
+        last: bool,
+        small_last_frame: bool,
+    ) -> Option<Audio> {
+        let available = self.samples();
+        if available == 0 {
+            return None;
+        }
+        let samples = match frame_size {
+            0 => available,
+            frame_size if available >= frame_size => frame_size,
+            _ if last && small_last_frame => available,
+            frame_size if last => {
+                self.pad(frame_size);
+                frame_size
+            }
+            _ => return None,
+        };
+        Some(self.pop(samples))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffmpeg::format::sample::Type;
+
+    const FORMAT: Sample = Sample::I16(Type::Packed);
+
+    // моно кадр из 16-битных сэмплов
+    fn frame(samples: &[i16]) -> Audio {
+        let mut frame = Audio::new(FORMAT, samples.len(), ChannelLayout::MONO);
+        for (to, sample) in frame.data_mut(0).chunks_exact_mut(2).zip(samples) {
+            to.copy_from_slice(&sample.to_ne_bytes());
+        }
+        frame
+    }
+
+    fn samples(frame: &Audio) -> Vec<i16> {
+        frame.data(0)[..frame.samples() * 2]
+            .chunks_exact(2)
+            .map(|bytes| i16::from_ne_bytes([bytes[0], bytes[1]]))
+            .collect()
+    }
+
+    fn fifo(pushes: &[usize]) -> SampleFifo {
+        let mut fifo = SampleFifo::new(FORMAT, ChannelLayout::MONO);
+        let mut next = 1;
+        for &count in pushes {
+            let values = (next..next + count as i16).collect::<Vec<_>>();
+            next += count as i16;
+            fifo.push(&frame(&values));
+        }
+        fifo
+    }
+
+    #[test]
+    fn uneven_pushes_pop_as_frames() {
+        let mut fifo = fifo(&[3, 7, 1, 5]);
+        assert_eq!(fifo.samples(), 16);
+
+        let first = fifo.next_frame(4, false, false).unwrap();
+        assert_eq!(samples(&first), [1, 2, 3, 4]);
+        let mut sizes = vec![first.samples()];
+        while let Some(frame) = fifo.next_frame(4, false, false) {
+            sizes.push(frame.samples());
+        }
+        assert_eq!(sizes, [4, 4, 4, 4]);
+        assert_eq!(fifo.samples(), 0);
+    }
+
+    #[test]
+    fn waits_for_whole_frame_until_last() {
+        let mut fifo = fifo(&[6]);
+        assert_eq!(fifo.next_frame(4, false, false).unwrap().samples(), 4);
+        assert!(fifo.next_frame(4, false, false).is_none());
+        assert_eq!(fifo.samples(), 2);
+    }
+
+    #[test]
+    fn last_frame_is_padded_with_silence() {
+        let mut fifo = fifo(&[6]);
+        fifo.next_frame(4, true, false).unwrap();
+        let last = fifo.next_frame(4, true, false).unwrap();
+        assert_eq!(samples(&last), [5, 6, 0, 0]);
+        assert!(fifo.next_frame(4, true, false).is_none());
+    }
+
+    #[test]
+    fn last_frame_stays_short_with_small_last_frame() {
+        let mut fifo = fifo(&[6]);
+        fifo.next_frame(4, true, true).unwrap();
+        let last = fifo.next_frame(4, true, true).unwrap();
+        assert_eq!(samples(&last), [5, 6]);
+        assert!(fifo.next_frame(4, true, true).is_none());
+    }
+
+    #[test]
+    fn any_frame_size_takes_everything() {
+        let mut fifo = fifo(&[3, 2]);
+        assert_eq!(
+            samples(&fifo.next_frame(0, false, false).unwrap()),
+            [1, 2, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn unsigned_silence_is_middle() {
+        let mut fifo = SampleFifo::new(Sample::U8(Type::Planar), ChannelLayout::STEREO);
+        fifo.pad(3);
+        let frame = fifo.pop(3);
+        assert_eq!(frame.data(0)[..3], [0x80, 0x80, 0x80]);
+        assert_eq!(frame.data(1)[..3], [0x80, 0x80, 0x80]);
+    }
+}
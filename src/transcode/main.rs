@@ -0,0 +1,93 @@
+extern crate ffmpeg_next as ffmpeg;
+
+mod audio;
+mod fifo;
+mod options;
+mod video;
+
+use anyhow::{anyhow, Context, Result};
+use audio::AudioTranscoder;
+use ffmpeg::codec::packet::Packet;
+use ffmpeg::format::{self, input, output};
+use ffmpeg::media::Type;
+use ffmpeg::{encoder, Rational};
+use options::Options;
+use video::VideoTranscoder;
+
+// перекодирует файл в H.264 + AAC (mp4, mkv) или VP9 + Opus (webm)
+// transcode movie.avi movie.mp4 --crf 23 --audio-bitrate 128k --scale 1280x0
+// берутся лучший видео и лучший аудио поток, остальные потоки не копируются
+fn main() -> Result<()> {
+    // регистриует все доступные форматы, кодеки и т.д.
+    ffmpeg::init().unwrap();
+
+    let options = Options::parse()?;
+
+    let mut ictx = input(&options.input)?;
+    // формат выходного файла выбирается по расширению
+    let mut octx = output(&options.output)?;
+
+    let mut video = match ictx.streams().best(Type::Video) {
+        Some(stream) => Some(VideoTranscoder::new(&stream, &mut octx, &options)?),
+        None => None,
+    };
+    let mut audio = match ictx.streams().best(Type::Audio) {
+        Some(stream) => Some(AudioTranscoder::new(&stream, &mut octx, &options)?),
+        None => None,
+    };
+    if video.is_none() && audio.is_none() {
+        return Err(anyhow!(
+            "{} has no video or audio to transcode",
+            options.input
+        ));
+    }
+    octx.set_metadata(ictx.metadata().to_owned());
+
+    octx.write_header()
+        .with_context(|| format!("couldn't write header of {}", options.output))?;
+
+    for (stream, packet) in ictx.packets() {
+        let index = stream.index();
+        if let Some(video) = video.as_mut().filter(|video| video.input_index == index) {
+            video.send_packet(&packet, &mut octx)?;
+        } else if let Some(audio) = audio.as_mut().filter(|audio| audio.input_index == index) {
+            audio.send_packet(&packet, &mut octx)?;
+        }
+    }
+
+    if let Some(video) = video.as_mut() {
+        video.finish(&mut octx)?;
+    }
+    if let Some(audio) = audio.as_mut() {
+        audio.finish(&mut octx)?;
+    }
+    octx.write_trailer()
+        .with_context(|| format!("couldn't finish {}", options.output))?;
+
+    println!(
+        "{}: video {}, audio {}",
+        options.output,
+        video.map_or("none".to_string(), |_| options.video_codec.to_string()),
+        audio.map_or("none".to_string(), |_| options.audio_codec.to_string()),
+    );
+    Ok(())
+}
+
+// забирает у энкодера готовые пакеты и пишет их в выходной файл
+// time_base это time base энкодера, муксер мог выбрать для потока свой
+pub fn write_packets(
+    encoder: &mut encoder::Encoder,
+    time_base: Rational,
+    output_index: usize,
+    octx: &mut format::context::Output,
+) -> Result<()> {
+    let mut packet = Packet::empty();
+    while encoder.receive_packet(&mut packet).is_ok() {
+        packet.set_stream(output_index);
+        packet.rescale_ts(time_base, octx.stream(output_index).unwrap().time_base());
+        packet
+            .write_interleaved(octx)
+            .context("couldn't write packet")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use anyhow::{anyhow, Result};
+use ffmpeg::codec::Id;
+use ffmpeg::encoder;
+use fftut::args::value;
+use fftut::decode::{DecoderBuilder, ErrorPolicy};
+use fftut::scale::Scaling;
+use std::env;
+use std::fmt;
+use std::path::Path;
+
+// параметры командной строки
+pub struct Options {
+    pub input: String,
+    pub output: String,
+    pub video_codec: EncoderChoice,
+    pub audio_codec: EncoderChoice,
+    // постоянное качество видео, у x264 и VP9 меньше значит лучше
+    pub crf: Option<u32>,
+    // битрейты в битах в секунду
+    pub video_bit_rate: Option<usize>,
+    pub audio_bit_rate: Option<usize>,
+    pub decoder: DecoderBuilder,
+    pub scaling: Scaling,
+    pub error_policy: ErrorPolicy,
+}
+
+impl Options {
+    pub fn parse() -> Result<Options> {
+        let mut files = Vec::new();
+        let mut video_codec = None;
+        let mut audio_codec = None;
+        let mut crf = None;
+        let mut video_bit_rate = None;
+        let mut audio_bit_rate = None;
+        let mut decoder = DecoderBuilder::new();
+        let mut scaling = Scaling::new();
+        let mut error_policy = ErrorPolicy::Skip;
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--vcodec" => video_codec = Some(value(&mut args, &arg)?),
+                "--acodec" => audio_codec = Some(value(&mut args, &arg)?),
+                "--crf" => {
+                    let value = value(&mut args, &arg)?;
+                    let quality = value
+                        .parse()
+                        .map_err(|_| anyhow!("invalid crf {}", value))?;
+                    crf = Some(quality);
+                }
+                "--video-bitrate" => video_bit_rate = Some(bit_rate(&value(&mut args, &arg)?)?),
+                "--audio-bitrate" => audio_bit_rate = Some(bit_rate(&value(&mut args, &arg)?)?),
+                "--scale" | "--scaler" => {
+                    scaling = scaling.option(&arg, &value(&mut args, &arg)?)?
+                }
+                "--threads" | "--threading" => {
+                    decoder = decoder.option(&arg, &value(&mut args, &arg)?)?
+                }
+                "--strict" => error_policy = ErrorPolicy::Abort,
+                _ if arg.starts_with("--") => return Err(anyhow!("unknown option {}", arg)),
+                _ => files.push(arg),
+            }
+        }
+
+        if files.len() != 2 {
+            return Err(anyhow!(
+                "usage: transcode <input> <output.mp4|mkv|webm> [--vcodec name] [--acodec name] \
+                 [--crf n] [--video-bitrate 2M] [--audio-bitrate 128k] [--scale WxH]"
+            ));
+        }
+        let output = files.pop().unwrap();
+        let input = files.pop().unwrap();
+
+        // WebM принимает только VP8/VP9/AV1 и Vorbis/Opus, остальным контейнерам даём H.264 и AAC
+        let webm = Path::new(&output)
+            .extension()
+            .map_or(false, |extension| extension == "webm");
+        let (video_default, audio_default) = if webm {
+            (
+                EncoderChoice::default(Id::VP9, "libvpx-vp9"),
+                EncoderChoice::default(Id::OPUS, "libopus"),
+            )
+        } else {
+            (
+                EncoderChoice::default(Id::H264, "libx264"),
+                EncoderChoice::default(Id::AAC, "aac"),
+            )
+        };
+
+        Ok(Options {
+            input,
+            output,
+            video_codec: video_codec.map_or(video_default, EncoderChoice::named),
+            audio_codec: audio_codec.map_or(audio_default, EncoderChoice::named),
+            crf,
+            video_bit_rate,
+            audio_bit_rate,
+            decoder,
+            scaling,
+            error_policy,
+        })
+    }
+}
+
+// какой энкодер искать: названный в --vcodec/--acodec или лучший для кодека по умолчанию
+pub struct EncoderChoice {
+    name: Option<String>,
+    id: Id,
+    // libx264 и libopus лучше встроенных энкодеров, но их может не быть в сборке ffmpeg
+    preferred: &'static str,
+}
+
+impl EncoderChoice {
+    fn default(id: Id, preferred: &'static str) -> EncoderChoice {
+        EncoderChoice {
+            name: None,
+            id,
+            preferred,
+        }
+    }
+
+    fn named(name: String) -> EncoderChoice {
+        EncoderChoice {
+            name: Some(name),
+            id: Id::None,
+            preferred: "",
+        }
+    }
+
+    pub fn find(&self) -> Result<ffmpeg::Codec> {
+        match &self.name {
+            Some(name) => {
+                encoder::find_by_name(name).ok_or_else(|| anyhow!("encoder {} not found", name))
+            }
+            None => encoder::find_by_name(self.preferred)
+                .or_else(|| encoder::find(self.id))
+                .ok_or_else(|| anyhow!("no encoder for {:?}", self.id)),
+        }
+    }
+}
+
+impl fmt::Display for EncoderChoice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name.as_deref().unwrap_or(self.preferred))
+    }
+}
+
+// битрейт как у ffmpeg: 128k, 2M или просто число бит в секунду
+fn bit_rate(value: &str) -> Result<usize> {
+    let (number, multiplier) = match value.chars().last() {
+        Some('k') | Some('K') => (&value[..value.len() - 1], 1_000.0),
+        Some('M') | Some('m') => (&value[..value.len() - 1], 1_000_000.0),
+        _ => (value, 1.0),
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|&number| number > 0.0)
+        .map(|number| (number * multiplier) as usize)
+        .ok_or_else(|| anyhow!("invalid bitrate {}", value))
+}
@@ -0,0 +1,190 @@
+use crate::options::Options;
+use crate::write_packets;
+use anyhow::{anyhow, Context, Result};
+use ffmpeg::codec::packet::Packet;
+use ffmpeg::format::stream::Stream;
+use ffmpeg::format::{self, Pixel};
+use ffmpeg::util::color::{Range, Space};
+use ffmpeg::util::frame::video::Video;
+use ffmpeg::{codec, decoder, encoder, Dictionary, Rational};
+use fftut::decode::DecodeErrors;
+use fftut::scale::Scaler;
+
+// видео поток: декодер -> swscale -> энкодер
+pub struct VideoTranscoder {
+    pub input_index: usize,
+    output_index: usize,
+    input_time_base: Rational,
+    decoder: decoder::Video,
+    scaler: Scaler,
+    encoder: encoder::video::Encoder,
+    errors: DecodeErrors,
+}
+
+impl VideoTranscoder {
+    // открывает декодер входного потока и добавляет в выходной файл поток с энкодером
+    pub fn new(
+        stream: &Stream,
+        octx: &mut format::context::Output,
+        options: &Options,
+    ) -> Result<VideoTranscoder> {
+        let decoder = options.decoder.video(stream)?;
+        let scaling = options.scaling.even_size(decoder.width(), decoder.height());
+        let (width, height) = scaling.size(decoder.width(), decoder.height());
+        let scaler = scaling.context(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            Pixel::YUV420P,
+        )?;
+
+        let codec = options.video_codec.find()?;
+        let global_header = octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
+        let mut output_stream = octx.add_stream(codec)?;
+        let output_index = output_stream.index();
+        let mut encoder = output_stream.codec().encoder().video()?;
+        encoder.set_width(width);
+        encoder.set_height(height);
+        encoder.set_aspect_ratio(aspect_ratio(
+            decoder.aspect_ratio(),
+            (decoder.width(), decoder.height()),
+            (width, height),
+        ));
+        encoder.set_format(Pixel::YUV420P);
+        // swscale отдаёт YUV в BT.601 с ограниченным диапазоном, так и помечаем
+        encoder.set_colorspace(Space::BT470BG);
+        encoder.set_color_range(Range::MPEG);
+        // время кадров не пересчитываем, у энкодера тот же time base что у входного потока
+        encoder.set_time_base(stream.time_base());
+        let frame_rate = stream.avg_frame_rate();
+        if frame_rate.numerator() > 0 {
+            encoder.set_frame_rate(Some(frame_rate));
+        }
+        if global_header {
+            encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+
+        // качество задаётся либо постоянным качеством (CRF), либо битрейтом
+        let mut settings = Dictionary::new();
+        if let Some(crf) = options.crf {
+            settings.set("crf", &crf.to_string());
+        }
+        match options.video_bit_rate {
+            Some(bit_rate) => encoder.set_bit_rate(bit_rate),
+            // libvpx без битрейта считает его заданным по умолчанию, а не выключенным
+            None if options.crf.is_some() => encoder.set_bit_rate(0),
+            None => {}
+        }
+        let encoder = encoder
+            .open_as_with(codec, settings)
+            .with_context(|| format!("couldn't open video encoder {}", options.video_codec))?;
+        output_stream.set_parameters(&encoder);
+
+        Ok(VideoTranscoder {
+            input_index: stream.index(),
+            output_index,
+            input_time_base: stream.time_base(),
+            decoder,
+            scaler,
+            encoder,
+            errors: DecodeErrors::new(options.error_policy),
+        })
+    }
+
+    pub fn send_packet(
+        &mut self,
+        packet: &Packet,
+        octx: &mut format::context::Output,
+    ) -> Result<()> {
+        if let Some(error) = self.errors.send_packet(&mut self.decoder, packet)? {
+            eprintln!("corrupt video packet skipped: {}", error);
+        }
+        self.receive_frames(octx)
+    }
+
+    // забирает из декодера кадры, которые он придержал до конца файла, и опустошает энкодер
+    pub fn finish(&mut self, octx: &mut format::context::Output) -> Result<()> {
+        self.decoder.send_eof()?;
+        self.receive_frames(octx)?;
+        self.encoder.send_eof()?;
+        write_packets(
+            &mut self.encoder,
+            self.input_time_base,
+            self.output_index,
+            octx,
+        )
+    }
+
+    fn receive_frames(&mut self, octx: &mut format::context::Output) -> Result<()> {
+        let mut decoded = Video::empty();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let mut scaled = Video::empty();
+            self.scaler.run(&decoded, &mut scaled)?;
+            scaled.set_pts(decoded.timestamp());
+            self.encoder
+                .send_frame(&scaled)
+                .map_err(|e| anyhow!("couldn't encode video frame: {}", e))?;
+            write_packets(
+                &mut self.encoder,
+                self.input_time_base,
+                self.output_index,
+                octx,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// SAR выходного потока, с которым картинка на экране остаётся той же формы, что у исходной:
+// --scale и отрезанная нечётная строка меняют размер в точках, а форма точек должна это учесть
+// неизвестный SAR у исходного потока считаем квадратным, как плееры
+fn aspect_ratio(sar: Rational, from: (u32, u32), to: (u32, u32)) -> Rational {
+    let (sar_num, sar_den) = if sar.numerator() > 0 && sar.denominator() > 0 {
+        (i64::from(sar.numerator()), i64::from(sar.denominator()))
+    } else {
+        (1, 1)
+    };
+    let num = i64::from(from.0) * sar_num * i64::from(to.1);
+    let den = i64::from(from.1.max(1)) * sar_den * i64::from(to.0.max(1));
+    let divisor = gcd(num, den);
+    let (num, den) = (num / divisor, den / divisor);
+    if num <= i64::from(i32::MAX) && den <= i64::from(i32::MAX) {
+        Rational::new(num as i32, den as i32)
+    } else {
+        Rational::from(num as f64 / den as f64)
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.max(1)
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aspect_ratio_keeps_display_shape() {
+        let square = Rational::new(1, 1);
+        assert_eq!(aspect_ratio(square, (1920, 1080), (640, 360)), square);
+        // DVD 4:3 с неквадратными точками в 640x480 становится квадратным
+        assert_eq!(
+            aspect_ratio(Rational::new(8, 9), (720, 480), (640, 480)),
+            square
+        );
+        // сжатую по ширине картинку растягивают широкие точки
+        assert_eq!(
+            aspect_ratio(square, (1280, 720), (640, 720)),
+            Rational::new(2, 1)
+        );
+        // неизвестный SAR считается квадратным
+        assert_eq!(
+            aspect_ratio(Rational::new(0, 1), (852, 480), (852, 480)),
+            square
+        );
+    }
+}
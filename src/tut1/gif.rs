@@ -0,0 +1,147 @@
+use crate::options::Options;
+use anyhow::{anyhow, Context, Result};
+use ffmpeg::format::{input, Pixel};
+use ffmpeg::media::Type;
+use ffmpeg::rescale;
+use ffmpeg::util::frame::video::Video;
+use fftut::animation::Animation;
+use fftut::decode::{drain, start_time, DecodeErrors};
+use fftut::rotation::Rotation;
+use fftut::snapshot::frame_image;
+use std::path::{Path, PathBuf};
+
+// без --scale GIF уменьшается до этого размера, в полном HD он весит десятки мегабайт
+const BOUNDS: (u32, u32) = (480, 480);
+
+// кусок видео для --gif start,duration
+pub struct Clip {
+    // секунды от начала файла
+    start: f64,
+    duration: f64,
+    // кадров в секунду в GIF, --fps, по умолчанию 10
+    fps: f64,
+}
+
+impl Clip {
+    // значение --gif: start,duration в секундах
+    pub fn parse(value: &str, fps: f64) -> Result<Clip> {
+        let mut parts = value
+            .splitn(2, ',')
+            .map(|part| part.parse::<f64>().ok().filter(|&seconds| seconds >= 0.0));
+        match (parts.next().flatten(), parts.next().flatten()) {
+            (Some(start), Some(duration)) if duration > 0.0 => Ok(Clip {
+                start,
+                duration,
+                fps,
+            }),
+            _ => Err(anyhow!(
+                "gif must be start,duration in seconds, for example 12.5,3, not {}",
+                value
+            )),
+        }
+    }
+}
+
+// делает из куска видео анимированный GIF в текущей папке: movie_12.500.gif
+// кадры берутся с частотой fps: для каждого момента GIF последний кадр видео,
+// который к этому моменту уже показан, так что и видео с меньшей частотой идёт с нормальной скоростью
+pub fn export(path: &str, clip: &Clip, options: &Options) -> Result<()> {
+    let mut ictx = input(&path)?;
+    let stream = ictx
+        .streams()
+        .best(Type::Video)
+        .ok_or(ffmpeg::Error::StreamNotFound)?;
+    let video_stream_index = stream.index();
+    let time_base = stream.time_base();
+    let rotation = Rotation::of(&stream);
+    let mut decoder = options.decoder.video(&stream)?;
+
+    let mut scaling = options.scaling;
+    if !scaling.is_sized() {
+        scaling = scaling.fit(decoder.width(), decoder.height(), BOUNDS);
+    }
+    let mut scaler = scaling.context(
+        decoder.format(),
+        decoder.width(),
+        decoder.height(),
+        Pixel::RGB24,
+    )?;
+
+    // start считается от начала файла, а время кадров от нуля
+    let file_start = start_time(&ictx);
+    let origin = file_start as f64 * f64::from(rescale::TIME_BASE);
+
+    // seek без индекса потока ждёт время в AV_TIME_BASE и попадает на ключевой кадр до позиции
+    let position = file_start + (clip.start / f64::from(rescale::TIME_BASE)).round() as i64;
+    ictx.seek(position, ..position)
+        .with_context(|| format!("couldn't seek to {:.3}s", clip.start))?;
+
+    let mut animation = Animation::new(clip.fps)?;
+    let end = clip.start + clip.duration;
+    // номер следующего кадра GIF, его момент start + index / fps
+    let mut index = 0;
+
+    // возвращает true, когда клип закончился
+    let mut receive_and_process_decoded_frames =
+        |decoder: &mut ffmpeg::decoder::Video| -> Result<bool, ffmpeg::Error> {
+            let mut decoded = Video::empty();
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                let seconds = match decoded.timestamp() {
+                    Some(timestamp) => timestamp as f64 * f64::from(time_base) - origin,
+                    None => continue,
+                };
+                if seconds >= end {
+                    return Ok(true);
+                }
+                // кадры от ключевого до start нужны только декодеру
+                let moment = |index: usize| clip.start + index as f64 / clip.fps;
+                if seconds < moment(index) {
+                    continue;
+                }
+
+                let mut rgb_frame = Video::empty();
+                scaler.run(&decoded, &mut rgb_frame)?;
+                let image = rotation.apply(frame_image(&rgb_frame));
+                // если видео реже GIF, один кадр видео занимает несколько кадров GIF
+                while moment(index) <= seconds && moment(index) < end {
+                    animation.push(image.clone());
+                    index += 1;
+                }
+            }
+            Ok(false)
+        };
+
+    let mut errors = DecodeErrors::new(options.error_policy);
+    let mut finished = false;
+    for (stream, packet) in ictx.packets() {
+        if stream.index() != video_stream_index {
+            continue;
+        }
+        if let Some(error) = errors.send_packet(&mut decoder, &packet)? {
+            eprintln!("corrupt video packet skipped: {}", error);
+        }
+        if receive_and_process_decoded_frames(&mut decoder)? {
+            finished = true;
+            break;
+        }
+    }
+    // клип доходит до конца файла, забираем кадры, придержанные декодером
+    if !finished {
+        drain(&mut decoder, |decoder| {
+            receive_and_process_decoded_frames(decoder).map(|_| ())
+        })?;
+    }
+    if animation.is_empty() {
+        return Err(anyhow!("no video frames after {:.3}s", clip.start));
+    }
+
+    let name = Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "clip".to_string());
+    let output = PathBuf::from(format!("{}_{:.3}.gif", name, clip.start));
+    let frames = animation.len();
+    animation.save(&output)?;
+    println!("{}: {} frames", output.display(), frames);
+    Ok(())
+}
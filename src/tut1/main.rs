@@ -1,99 +1,132 @@
 extern crate ffmpeg_next as ffmpeg;
 
-use anyhow::{anyhow, Context as AContext, Result};
+mod gif;
+mod options;
+
+use anyhow::Result;
 use ffmpeg::format::{input, Pixel};
 use ffmpeg::media::Type;
-use ffmpeg::software::scaling::{context::Context, flag::Flags};
 use ffmpeg::util::frame::video::Video;
-use std::env;
+use fftut::decode::{drain, DecodeErrors};
+use fftut::playlist::Playlist;
+use fftut::rotation::Rotation;
+use fftut::snapshot::save_rotated_frame;
+use options::Options;
+use std::path::Path;
 
 fn main() -> Result<()> {
     // регистриует все доступные форматы, кодеки и т.д.
     ffmpeg::init().unwrap();
 
+    let options = Options::parse()?;
+    let mut playlist = Playlist::new(&options.inputs)?;
+    // кадры нескольких файлов не должны перезаписывать друг друга: movie_frame30.jpeg
+    let several = playlist.len() > 1;
+
+    loop {
+        let path = playlist.current();
+        // с --gif start,duration вместо кадров в jpeg из куска видео делается анимированный GIF
+        let result = match &options.clip {
+            Some(clip) => gif::export(path, clip, &options),
+            None if several => extract_frames(path, &format!("{}_", file_stem(path)), &options),
+            None => extract_frames(path, "", &options),
+        };
+        // битый файл не повод бросать остальные
+        if let Err(e) = result {
+            eprintln!("{}: {:#}", path, e);
+        }
+        if !playlist.next() {
+            return Ok(());
+        }
+    }
+}
+
+// сохраняет каждый 30-й кадр в prefixframeN.jpeg
+fn extract_frames(path: &str, prefix: &str, options: &Options) -> Result<()> {
     // открываем указанный input сюда идёт всё то что можно указать через -i
     // по сути читает header файла или подобные действия получает информацию о формате input
-    if let Ok(mut ictx) = input(
-        &env::args()
-            .nth(1)
-            .ok_or_else(|| anyhow!("no input specified"))?,
-    ) {
-        // дамп информации о контексте input'а, тертий параметр не обязательный
-        ffmpeg::format::context::input::dump(&ictx, 0, Some(env::args().nth(1).unwrap().as_str()));
-
-        // даелее мы смотрим доступные потоки, конкретно тут мы ищем "лучший" видео поток
-        let input = ictx
-            .streams()
-            .best(Type::Video)
-            .ok_or(ffmpeg::Error::StreamNotFound)?;
-        let video_stream_index = input.index();
-
-        // находим декодер (кодек) по id видео потока
-        // под копотом в функции .video() вызывает avcodec_find_decoder()
-        // и потом открывается сам коде через avcodec_open2()
-        let mut decoder = input.codec().decoder().video()?;
-
-        // определяем из какого формата в какой переводим
-        let mut scaler = Context::get(
-            decoder.format(),
-            decoder.width(),
-            decoder.height(),
-            Pixel::RGB24,
-            decoder.width(),
-            decoder.height(),
-            Flags::BILINEAR,
-        )?;
-
-        let mut frame_index = 0;
-
-        // функция для докодирования фреймов и записи их в файл
-        let mut receive_and_process_decoded_frames =
-            |decoder: &mut ffmpeg::decoder::Video| -> Result<(), ffmpeg::Error> {
-                // здесь происходит аллокация пустого фрейма через av_frame_alloc()
-                let mut decoded = Video::empty();
-                // пытаемся получить готовый фрейм из декодера через avcodec_receive_frame()
-                while decoder.receive_frame(&mut decoded).is_ok() {
-                    frame_index += 1;
-
-                    if frame_index % 30 != 0 {
-                        continue;
-                    }
-
-                    // здесь происходит аллокация пустого фрейма куда мы поместим модифицированный фрейм
-                    let mut rgb_frame = Video::empty();
-                    // переводим фрейм в нужный формат sws_scale()
-                    scaler.run(&decoded, &mut rgb_frame)?;
-                    save_file(&rgb_frame, frame_index).unwrap();
+    let mut ictx = input(&path)?;
+    // дамп информации о контексте input'а, тертий параметр не обязательный
+    ffmpeg::format::context::input::dump(&ictx, 0, Some(path));
+
+    // даелее мы смотрим доступные потоки, конкретно тут мы ищем "лучший" видео поток
+    let input = ictx
+        .streams()
+        .best(Type::Video)
+        .ok_or(ffmpeg::Error::StreamNotFound)?;
+    let video_stream_index = input.index();
+
+    // находим декодер (кодек) по id видео потока
+    // под копотом в функции .video() вызывает avcodec_find_decoder()
+    // и потом открывается сам коде через avcodec_open2()
+    let mut decoder = options.decoder.video(&input)?;
+
+    // определяем из какого формата в какой переводим
+    let mut scaler = options.scaling.context(
+        decoder.format(),
+        decoder.width(),
+        decoder.height(),
+        Pixel::RGB24,
+    )?;
+
+    // видео с телефона надо повернуть так, как его держали при съёмке
+    let rotation = Rotation::of(&input);
+
+    let mut frame_index = 0;
+
+    // функция для докодирования фреймов и записи их в файл
+    let mut receive_and_process_decoded_frames =
+        |decoder: &mut ffmpeg::decoder::Video| -> Result<(), ffmpeg::Error> {
+            // здесь происходит аллокация пустого фрейма через av_frame_alloc()
+            let mut decoded = Video::empty();
+            // пытаемся получить готовый фрейм из декодера через avcodec_receive_frame()
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                frame_index += 1;
+
+                if frame_index % 30 != 0 {
+                    continue;
                 }
-                Ok(())
-            };
-
-        // читаем все пакеты из потока через av_read_frame()
-        for (stream, packet) in ictx.packets() {
-            // если пакет относится к видео
-            if stream.index() == video_stream_index {
-                // посылаем пакет в декодер avcodec_send_packet()
-                decoder.send_packet(&packet)?;
-                receive_and_process_decoded_frames(&mut decoder)?;
+
+                // здесь происходит аллокация пустого фрейма куда мы поместим модифицированный фрейм
+                let mut rgb_frame = Video::empty();
+                // переводим фрейм в нужный формат sws_scale()
+                scaler.run(&decoded, &mut rgb_frame)?;
+                save_rotated_frame(
+                    &rgb_frame,
+                    &rotation,
+                    format!("{}frame{}.jpeg", prefix, frame_index),
+                )
+                .unwrap();
             }
+            Ok(())
+        };
+
+    // битые пакеты пропускаем и считаем, с --strict останавливаемся на первом же
+    let mut errors = DecodeErrors::new(options.error_policy);
+
+    // читаем все пакеты из потока через av_read_frame()
+    for (stream, packet) in ictx.packets() {
+        // если пакет относится к видео
+        if stream.index() == video_stream_index {
+            // посылаем пакет в декодер avcodec_send_packet()
+            if let Some(error) = errors.send_packet(&mut decoder, &packet)? {
+                eprintln!("corrupt video packet skipped: {}", error);
+            }
+            receive_and_process_decoded_frames(&mut decoder)?;
         }
-        decoder.send_eof()?;
-        receive_and_process_decoded_frames(&mut decoder)?;
+    }
+    // забираем из декодера кадры, которые он придержал до конца файла
+    drain(&mut decoder, &mut receive_and_process_decoded_frames)?;
+    if errors.skipped() > 0 {
+        eprintln!("{} corrupt packets skipped", errors.skipped());
     }
 
     Ok(())
 }
 
-fn save_file(frame: &Video, index: usize) -> Result<()> {
-    let mut buffer: Vec<u8> =
-        format!("P6\n{} {}\n255\n", frame.width(), frame.height()).into_bytes();
-    buffer.extend_from_slice(frame.data(0));
-    image::save_buffer(
-        format!("frame{}.jpeg", index),
-        &buffer,
-        frame.width(),
-        frame.height(),
-        image::ColorType::Rgb8,
-    )
-    .context("couldn't save frame")
+fn file_stem(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "frames".to_string())
 }
@@ -0,0 +1,71 @@
+use crate::gif::Clip;
+use anyhow::{anyhow, Result};
+use fftut::args::value;
+use fftut::decode::{DecoderBuilder, ErrorPolicy};
+use fftut::scale::Scaling;
+use std::env;
+
+// параметры командной строки
+pub struct Options {
+    // файлы и плейлисты по порядку
+    pub inputs: Vec<String>,
+    // кусок видео для GIF из --gif и --fps, без --gif кадры сохраняются в jpeg
+    pub clip: Option<Clip>,
+    pub error_policy: ErrorPolicy,
+    // потоки декодирования из --threads и --threading
+    pub decoder: DecoderBuilder,
+    // размер и алгоритм перевода кадров из --scale и --scaler
+    pub scaling: Scaling,
+}
+
+impl Options {
+    pub fn parse() -> Result<Options> {
+        let mut inputs = Vec::new();
+        let mut gif = None;
+        let mut fps = 10.0;
+        let mut error_policy = ErrorPolicy::Skip;
+        let mut decoder = DecoderBuilder::new();
+        let mut scaling = Scaling::new();
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--gif" => gif = Some(value(&mut args, &arg)?),
+                "--fps" => {
+                    let value = value(&mut args, &arg)?;
+                    fps = value
+                        .parse()
+                        .map_err(|_| anyhow!("invalid fps {}", value))?;
+                }
+                "--strict" => error_policy = ErrorPolicy::Abort,
+                "--scale" | "--scaler" => {
+                    scaling = scaling.option(&arg, &value(&mut args, &arg)?)?
+                }
+                "--threads" | "--threading" => {
+                    decoder = decoder.option(&arg, &value(&mut args, &arg)?)?
+                }
+                _ if arg.starts_with("--") => return Err(anyhow!("unknown option {}", arg)),
+                _ => inputs.push(arg),
+            }
+        }
+
+        if inputs.is_empty() {
+            return Err(anyhow!(
+                "usage: tut1 <input>... [--gif start,duration] [--fps n] [--scale WxH] [--strict]"
+            ));
+        }
+        // --fps может стоять и после --gif, поэтому клип собирается после разбора
+        let clip = match gif {
+            Some(gif) => Some(Clip::parse(&gif, fps)?),
+            None => None,
+        };
+
+        Ok(Options {
+            inputs,
+            clip,
+            error_policy,
+            decoder,
+            scaling,
+        })
+    }
+}
@@ -1,117 +1,228 @@
 extern crate ffmpeg_next as ffmpeg;
 
+mod options;
+
 use anyhow::{anyhow, Context as AContext, Result};
 use ffmpeg::format::{input, Pixel};
 use ffmpeg::media::Type;
-use ffmpeg::software::scaling::{context::Context, flag::Flags};
 use ffmpeg::util::frame::video::Video;
+use fftut::decode::{drain, DecodeErrors};
+use fftut::playlist::Playlist;
+use fftut::snapshot::{frame_ref, save_screenshot};
+use options::Options;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::render::{TextureCreator, WindowCanvas};
 use sdl2::video::WindowContext;
-use std::env;
+
+// чем закончилось проигрывание одного элемента плейлиста
+enum Transition {
+    Next,
+    Previous,
+    Quit,
+}
 
 fn main() -> Result<()> {
     // регистриует все доступные форматы, кодеки и т.д.
     ffmpeg::init().unwrap();
 
+    let options = Options::parse()?;
+    let mut playlist = Playlist::new(&options.inputs)?;
+
+    // по сути какой-то синглтон который следит за тем что бы у нас не было несколько контекстов
+    let sdl_context = sdl2::init().map_err(|e| anyhow!(e))?;
+    // кадры переводятся в YUV по BT.601 (см. Scaler), а SDL по умолчанию у текстур
+    // выше 576 строк считает YUV в BT.709, и у HD видео едут оттенки
+    sdl2::hint::set("SDL_YUV_CONVERSION_MODE", "BT601");
+    // по сути это SDL_init(SDL_INIT_VIDEO)
+    let video_subsystem = sdl_context.video().map_err(|e| anyhow!(e))?;
+    let mut event_pump = sdl_context.event_pump().map_err(|e| anyhow!(e))?;
+
+    // окно создаётся по первому файлу и переходит от файла к файлу
+    let mut canvas: Option<WindowCanvas> = None;
+
+    loop {
+        let path = playlist.current().to_string();
+        let transition = match play(
+            &path,
+            &options,
+            &video_subsystem,
+            &mut canvas,
+            &mut event_pump,
+        ) {
+            Ok(transition) => transition,
+            // битый элемент плейлиста не повод останавливать весь плейлист
+            Err(e) => {
+                eprintln!("{}: {:#}", path, e);
+                Transition::Next
+            }
+        };
+        match transition {
+            Transition::Next => {
+                if !playlist.next() {
+                    break;
+                }
+            }
+            Transition::Previous => playlist.previous(),
+            Transition::Quit => break,
+        }
+    }
+
+    Ok(())
+}
+
+// показывает один файл, N и P переключают на следующий и предыдущий
+fn play(
+    path: &str,
+    options: &Options,
+    video_subsystem: &sdl2::VideoSubsystem,
+    canvas: &mut Option<WindowCanvas>,
+    event_pump: &mut sdl2::EventPump,
+) -> Result<Transition> {
     // открываем указанный input сюда идёт всё то что можно указать через -i
     // по сути читает header файла или подобные действия получает информацию о формате input
-    if let Ok(mut ictx) = input(
-        &env::args()
-            .nth(1)
-            .ok_or_else(|| anyhow!("no input specified"))?,
-    ) {
-        // дамп информации о контексте input'а, тертий параметр не обязательный
-        ffmpeg::format::context::input::dump(&ictx, 0, Some(env::args().nth(1).unwrap().as_str()));
-
-        // даелее мы смотрим доступные потоки, конкретно тут мы ищем "лучший" видео поток
-        let input = ictx
-            .streams()
-            .best(Type::Video)
-            .ok_or(ffmpeg::Error::StreamNotFound)?;
-
-        let video_stream_index = input.index();
-
-        // находим декодер (кодек) по id видео потока
-        // под копотом в функции .video() вызывает avcodec_find_decoder()
-        // и потом открывается сам коде через avcodec_open2()
-        let mut decoder = input.codec().decoder().video()?;
-
-        // определяем из какого формата в какой переводим
-        let mut context = Context::get(
-            decoder.format(),
-            decoder.width(),
-            decoder.height(),
-            Pixel::YUV420P,
-            decoder.width(),
-            decoder.height(),
-            Flags::BILINEAR,
-        )?;
-
-        // по сути какой-то синглтон который следит за тем что бы у нас не было несколько контекстов
-        let sdl_context = sdl2::init().map_err(|e| anyhow!(e))?;
-        // по сути это SDL_init(SDL_INIT_VIDEO)
-        let video_subsystem = sdl_context.video().map_err(|e| anyhow!(e))?;
-
-        // создаём окно в котором будем отображать информацию
-        let window = video_subsystem
-            .window("rust-sdl2 demo: Video", decoder.width(), decoder.height())
-            .position_centered()
-            .opengl()
-            .build()
-            .context("couldn't create window")?;
-
-        // создаём канвас в окне SDL_CreateRenderer()
-        let mut canvas = window
-            .into_canvas()
-            .build()
-            .context("couldn't create canvas")?;
-        let texture_creator = canvas.texture_creator();
-
-        // функция для докодирования фреймов и записи их в файл
-        let mut receive_and_process_decoded_frames =
-            |decoder: &mut ffmpeg::decoder::Video| -> Result<(), ffmpeg::Error> {
-                // здесь происходит аллокация пустого фрейма через av_frame_alloc()
-                let mut decoded = Video::empty();
-                // пытаемся получить готовый фрейм из декодера через avcodec_receive_frame()
-                while decoder.receive_frame(&mut decoded).is_ok() {
-                    // здесь происходит аллокация пустого фрейма куда мы поместим модифицированный фрейм
-                    let mut frame_to_display = Video::empty();
-                    // переводим фрейм в нужный формат sws_scale()
-                    context.run(&decoded, &mut frame_to_display)?;
-
-                    draw_frame(&mut frame_to_display, &mut canvas, &texture_creator).unwrap();
-                }
-                Ok(())
-            };
-
-        let mut event_pump = sdl_context.event_pump().map_err(|e| anyhow!(e))?;
-
-        // читаем все пакеты из потока через av_read_frame()
-        for (stream, packet) in ictx.packets() {
-            // если пакет относится к видео
-            if stream.index() == video_stream_index {
-                // посылаем пакет в декодер avcodec_send_packet()
-                decoder.send_packet(&packet)?;
-                receive_and_process_decoded_frames(&mut decoder)?;
+    let mut ictx = input(&path)?;
+    // дамп информации о контексте input'а, тертий параметр не обязательный
+    ffmpeg::format::context::input::dump(&ictx, 0, Some(path));
+
+    // даелее мы смотрим доступные потоки, конкретно тут мы ищем "лучший" видео поток
+    let input = ictx
+        .streams()
+        .best(Type::Video)
+        .ok_or(ffmpeg::Error::StreamNotFound)?;
+
+    let video_stream_index = input.index();
+    let video_time_base = input.time_base();
+
+    // находим декодер (кодек) по id видео потока
+    // под копотом в функции .video() вызывает avcodec_find_decoder()
+    // и потом открывается сам коде через avcodec_open2()
+    let mut decoder = options.decoder.video(&input)?;
+
+    // определяем из какого формата в какой переводим, размер и алгоритм задаются --scale и --scaler
+    // кадры сразу переводятся в размер окна, а окно не больше экрана
+    let bounds = video_subsystem
+        .display_usable_bounds(0)
+        .map(|bounds| bounds.size())
+        .unwrap_or((u32::MAX, u32::MAX));
+    let scaling = options
+        .scaling
+        .fit(decoder.width(), decoder.height(), bounds);
+    let (width, height) = scaling.size(decoder.width(), decoder.height());
+    let mut context = scaling.context(
+        decoder.format(),
+        decoder.width(),
+        decoder.height(),
+        Pixel::YUV420P,
+    )?;
+
+    match canvas {
+        // следующий файл показываем в том же окне, только подгоняем его размер
+        Some(canvas) => canvas
+            .window_mut()
+            .set_size(width, height)
+            .context("couldn't resize window")?,
+        None => {
+            // создаём окно в котором будем отображать информацию
+            let window = video_subsystem
+                .window("rust-sdl2 demo: Video", width, height)
+                .position_centered()
+                .opengl()
+                .build()
+                .context("couldn't create window")?;
+
+            // создаём канвас в окне SDL_CreateRenderer()
+            *canvas = Some(
+                window
+                    .into_canvas()
+                    .build()
+                    .context("couldn't create canvas")?,
+            );
+        }
+    }
+    let canvas = canvas.as_mut().unwrap();
+    let texture_creator = canvas.texture_creator();
+
+    // функция для докодирования фреймов и записи их в файл
+    // последний показанный кадр до масштабирования и его pts сохраняются в last_frame,
+    // чтобы скриншот был в исходном разрешении, а не в размере окна
+    let mut receive_and_process_decoded_frames = |decoder: &mut ffmpeg::decoder::Video,
+                                                  last_frame: &mut Option<(Video, i64)>|
+     -> Result<()> {
+        // здесь происходит аллокация пустого фрейма через av_frame_alloc()
+        let mut decoded = Video::empty();
+        // пытаемся получить готовый фрейм из декодера через avcodec_receive_frame()
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            // здесь происходит аллокация пустого фрейма куда мы поместим модифицированный фрейм
+            let mut frame_to_display = Video::empty();
+            // переводим фрейм в нужный формат sws_scale()
+            context.run(&decoded, &mut frame_to_display)?;
+
+            draw_frame(&mut frame_to_display, canvas, &texture_creator)?;
+            *last_frame = Some((frame_ref(&decoded)?, decoded.timestamp().unwrap_or(0)));
+        }
+        Ok(())
+    };
+
+    let mut last_frame = None;
+
+    // битые пакеты пропускаем и считаем, с --strict останавливаемся на первом же
+    let mut errors = DecodeErrors::new(options.error_policy);
+
+    // читаем все пакеты из потока через av_read_frame()
+    for (stream, packet) in ictx.packets() {
+        // если пакет относится к видео
+        if stream.index() == video_stream_index {
+            // посылаем пакет в декодер avcodec_send_packet()
+            if let Some(error) = errors.send_packet(&mut decoder, &packet)? {
+                eprintln!("corrupt video packet skipped: {}", error);
             }
-            if let Some(event) = event_pump.poll_event() {
-                match event {
-                    Event::Quit { .. }
-                    | Event::KeyDown {
-                        keycode: Some(Keycode::Escape),
-                        ..
-                    } => break,
-                    _ => {}
+            receive_and_process_decoded_frames(&mut decoder, &mut last_frame)?;
+        }
+        if let Some(event) = event_pump.poll_event() {
+            // при переключении доигрывать остатки из декодера не нужно
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return Ok(Transition::Quit),
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
+                    ..
+                } => return Ok(Transition::Next),
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } => return Ok(Transition::Previous),
+                // скриншот текущего кадра в исходном разрешении
+                Event::KeyDown {
+                    keycode: Some(Keycode::S),
+                    ..
+                } => {
+                    // не получилось сохранить (нет места, нет прав) не повод останавливать видео
+                    if let Some((frame, pts)) = &last_frame {
+                        let seconds = *pts as f64 * f64::from(video_time_base);
+                        match save_screenshot(frame, path, seconds) {
+                            Ok(path) => println!("saved {}", path.display()),
+                            Err(e) => eprintln!("couldn't save screenshot: {:#}", e),
+                        }
+                    }
                 }
+                _ => {}
             }
         }
-        decoder.send_eof()?;
+    }
+    // показываем кадры, которые декодер придержал до конца файла
+    drain(&mut decoder, |decoder| {
+        receive_and_process_decoded_frames(decoder, &mut last_frame)
+    })?;
+    if errors.skipped() > 0 {
+        eprintln!("{} corrupt packets skipped", errors.skipped());
     }
 
-    Ok(())
+    Ok(Transition::Next)
 }
 
 fn draw_frame(
@@ -122,25 +233,21 @@ fn draw_frame(
     let mut texture = texture_creator
         .create_texture_streaming(PixelFormatEnum::YV12, frame.width(), frame.height())
         .context("couldn't create texture")?;
+    // строки кадра выровнены и могут быть длиннее самой картинки,
+    // поэтому SDL копирует плоскости построчно с шагом строки кадра
+    // длину плоскостей цветности SDL сверяет с шаг * высота / 2, в том числе при нечётной высоте
+    let rows = frame.height() as usize;
     texture
-        .with_lock(None, |buffer: &mut [u8], _: usize| {
-            let mut index: usize = 0;
-            for (i, byte) in frame.data(0).iter().enumerate() {
-                buffer[i] = *byte;
-                index += 1;
-            }
-
-            for byte in frame.data(2) {
-                buffer[index] = *byte;
-                index += 1;
-            }
-
-            for byte in frame.data(1) {
-                buffer[index] = *byte;
-                index += 1;
-            }
-        })
-        .map_err(|e| anyhow!(e))?;
+        .update_yuv(
+            None,
+            &frame.data(0)[..frame.stride(0) * rows],
+            frame.stride(0),
+            &frame.data(1)[..frame.stride(1) * rows / 2],
+            frame.stride(1),
+            &frame.data(2)[..frame.stride(2) * rows / 2],
+            frame.stride(2),
+        )
+        .context("couldn't update texture")?;
     canvas.copy(&texture, None, None).map_err(|e| anyhow!(e))?;
     canvas.present();
     Ok(())
@@ -0,0 +1,53 @@
+use anyhow::{anyhow, Result};
+use fftut::args::value;
+use fftut::decode::{DecoderBuilder, ErrorPolicy};
+use fftut::scale::Scaling;
+use std::env;
+
+// параметры командной строки
+pub struct Options {
+    // файлы и плейлисты по порядку
+    pub inputs: Vec<String>,
+    pub error_policy: ErrorPolicy,
+    // потоки декодирования из --threads и --threading
+    pub decoder: DecoderBuilder,
+    // размер и алгоритм перевода кадров из --scale и --scaler
+    pub scaling: Scaling,
+}
+
+impl Options {
+    pub fn parse() -> Result<Options> {
+        let mut inputs = Vec::new();
+        let mut error_policy = ErrorPolicy::Skip;
+        let mut decoder = DecoderBuilder::new();
+        let mut scaling = Scaling::new();
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--strict" => error_policy = ErrorPolicy::Abort,
+                "--scale" | "--scaler" => {
+                    scaling = scaling.option(&arg, &value(&mut args, &arg)?)?
+                }
+                "--threads" | "--threading" => {
+                    decoder = decoder.option(&arg, &value(&mut args, &arg)?)?
+                }
+                _ if arg.starts_with("--") => return Err(anyhow!("unknown option {}", arg)),
+                _ => inputs.push(arg),
+            }
+        }
+
+        if inputs.is_empty() {
+            return Err(anyhow!(
+                "usage: tut3 <input>... [--scale WxH] [--scaler name] [--threads n] [--strict]"
+            ));
+        }
+
+        Ok(Options {
+            inputs,
+            error_policy,
+            decoder,
+            scaling,
+        })
+    }
+}
@@ -0,0 +1,129 @@
+use crate::options::Options;
+use anyhow::Result;
+use ffmpeg::format::{input, Pixel};
+use ffmpeg::media::Type;
+use ffmpeg::util::frame::video::Video;
+use fftut::decode::{drain, DecodeErrors};
+use std::fs;
+use std::time::{Duration, Instant};
+
+// --bench: декодируем видео так быстро как можем, без окна, звука и часов,
+// и переводим кадры в YUV420P как это делает плеер, с --scale и --scaler если они указаны
+// печатаем fps и перцентили времени декодирования и перевода одного кадра
+pub fn run(path: &str, options: &Options) -> Result<()> {
+    let mut ictx = input(&path)?;
+    let stream = ictx
+        .streams()
+        .best(Type::Video)
+        .ok_or(ffmpeg::Error::StreamNotFound)?;
+    let video_stream_index = stream.index();
+    let mut decoder = options.decoder.video(&stream)?;
+    let codec = decoder.id().name();
+
+    let mut scaler = options.scaling.context(
+        decoder.format(),
+        decoder.width(),
+        decoder.height(),
+        Pixel::YUV420P,
+    )?;
+
+    // с send/receive API пакет и кадр не соответствуют друг другу один к одному,
+    // поэтому время декодирования кадра это всё время в декодере с выхода предыдущего кадра
+    let mut decode_times = Vec::new();
+    let mut scale_times = Vec::new();
+    let mut in_decoder = Duration::default();
+
+    let mut receive_and_scale_frames = |decoder: &mut ffmpeg::decoder::Video,
+                                        in_decoder: &mut Duration|
+     -> Result<(), ffmpeg::Error> {
+        let mut decoded = Video::empty();
+        loop {
+            let receive_started = Instant::now();
+            let received = decoder.receive_frame(&mut decoded).is_ok();
+            *in_decoder += receive_started.elapsed();
+            if !received {
+                return Ok(());
+            }
+            decode_times.push(*in_decoder);
+            *in_decoder = Duration::default();
+
+            let scale_started = Instant::now();
+            let mut scaled = Video::empty();
+            scaler.run(&decoded, &mut scaled)?;
+            scale_times.push(scale_started.elapsed());
+        }
+    };
+
+    let mut errors = DecodeErrors::new(options.error_policy);
+    let started = Instant::now();
+    for (stream, packet) in ictx.packets() {
+        if stream.index() != video_stream_index {
+            continue;
+        }
+        let send_started = Instant::now();
+        errors.send_packet(&mut decoder, &packet)?;
+        in_decoder += send_started.elapsed();
+        receive_and_scale_frames(&mut decoder, &mut in_decoder)?;
+    }
+    drain(&mut decoder, |decoder| {
+        receive_and_scale_frames(decoder, &mut in_decoder)
+    })?;
+    let elapsed = started.elapsed().as_secs_f64();
+
+    let frames = decode_times.len();
+    println!(
+        "{}: {} {}x{}, {} frames in {:.2}s ({:.1} fps)",
+        path,
+        codec,
+        decoder.width(),
+        decoder.height(),
+        frames,
+        elapsed,
+        frames as f64 / elapsed
+    );
+    println!("  decode ms: {}", percentiles(&mut decode_times));
+    println!("  scale ms:  {}", percentiles(&mut scale_times));
+    if errors.skipped() > 0 {
+        println!("  corrupt packets skipped: {}", errors.skipped());
+    }
+    Ok(())
+}
+
+// пик памяти это VmHWM всего процесса, у второго файла плейлиста в нём и пик первого,
+// поэтому печатаем его один раз после всех файлов
+pub fn report_peak_memory() {
+    match peak_memory() {
+        Some(kib) => println!("peak memory: {:.1} MiB", kib as f64 / 1024.0),
+        None => println!("peak memory: unknown"),
+    }
+}
+
+fn percentiles(times: &mut [Duration]) -> String {
+    if times.is_empty() {
+        return "no frames".to_string();
+    }
+    times.sort();
+    let at = |percent: usize| {
+        let index = (times.len() * percent / 100).min(times.len() - 1);
+        times[index].as_secs_f64() * 1000.0
+    };
+    format!(
+        "p50 {:.2} p90 {:.2} p99 {:.2} max {:.2}",
+        at(50),
+        at(90),
+        at(99),
+        at(100)
+    )
+}
+
+// пик потребления памяти процессом в KiB, VmHWM из /proc есть только на Linux
+fn peak_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find(|line| line.starts_with("VmHWM:"))?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
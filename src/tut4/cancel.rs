@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+type Wake = Arc<dyn Fn() + Send + Sync>;
+
+// общий флаг остановки для потока чтения и потоков декодеров
+// очереди конвейера ждут на своих condvar, а флаг их не будит,
+// поэтому каждая очередь регистрирует здесь функцию, которая будит её ждущих при отмене
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<Inner>);
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    wakers: Mutex<Vec<Wake>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        // будим без блокировки списка, функции сами берут мьютексы очередей
+        let wakers = self.0.wakers.lock().unwrap().clone();
+        for wake in wakers {
+            wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    // wake вызывается один раз при отмене, или сразу, если отмена уже была
+    // чтобы пробуждение не потерялось, wake должна взять тот же мьютекс, под которым
+    // ждущий проверяет is_cancelled(), и только потом звать notify_all()
+    pub fn on_cancel<F>(&self, wake: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let wake: Wake = Arc::new(wake);
+        self.0.wakers.lock().unwrap().push(wake.clone());
+        if self.is_cancelled() {
+            wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn cancel_calls_wakers() {
+        let cancel = CancellationToken::new();
+        let woken = Arc::new(AtomicUsize::new(0));
+        let counter = woken.clone();
+        cancel.on_cancel(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(!cancel.is_cancelled());
+        assert_eq!(woken.load(Ordering::SeqCst), 0);
+
+        // клон токена отменяет тот же конвейер
+        cancel.clone().cancel();
+        assert!(cancel.is_cancelled());
+        assert_eq!(woken.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn waker_after_cancel_is_called_at_once() {
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let woken = Arc::new(AtomicUsize::new(0));
+        let counter = woken.clone();
+        cancel.on_cancel(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(woken.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,71 @@
+use ffmpeg::format::context::Input;
+
+// PageUp в первые секунды главы переходит на предыдущую главу, позже в начало текущей
+const RESTART_THRESHOLD: f64 = 3.0;
+// после перехода на главу первый кадр может оказаться чуть раньше её начала из-за округления
+const START_TOLERANCE: f64 = 0.1;
+
+pub struct Chapter {
+    // начало главы в секундах
+    pub start: f64,
+    pub title: Option<String>,
+}
+
+// главы из контейнера (MKV, MP4 и т.д.), отсортированные по началу
+pub struct Chapters(Vec<Chapter>);
+
+impl Chapters {
+    pub fn new(ictx: &Input) -> Chapters {
+        let mut chapters = ictx
+            .chapters()
+            .map(|chapter| Chapter {
+                start: chapter.start() as f64 * f64::from(chapter.time_base()),
+                title: chapter.metadata().get("title").map(str::to_string),
+            })
+            .collect::<Vec<_>>();
+        chapters.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+        Chapters(chapters)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Chapter> {
+        self.0.iter()
+    }
+
+    // глава, в которой находится position
+    pub fn index_at(&self, position: f64) -> Option<usize> {
+        self.0
+            .iter()
+            .rposition(|chapter| chapter.start <= position + START_TOLERANCE)
+    }
+
+    pub fn next(&self, position: f64) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|chapter| chapter.start > position + START_TOLERANCE)
+    }
+
+    // как у DVD плееров: в начале главы это предыдущая глава, иначе начало текущей
+    pub fn previous(&self, position: f64) -> Option<usize> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let index = self
+            .0
+            .iter()
+            .rposition(|chapter| chapter.start < position - RESTART_THRESHOLD);
+        Some(index.unwrap_or(0))
+    }
+
+    pub fn start(&self, index: usize) -> f64 {
+        self.0[index].start
+    }
+
+    // строка для OSD: "chapter 2/12: Intro"
+    pub fn describe(&self, index: usize) -> String {
+        let title = match &self.0[index].title {
+            Some(title) => title.clone(),
+            None => format!("Chapter {}", index + 1),
+        };
+        format!("chapter {}/{}: {}", index + 1, self.0.len(), title)
+    }
+}
@@ -0,0 +1,177 @@
+use crate::filter;
+use anyhow::{anyhow, Result};
+use ffmpeg::util::frame::video::Video;
+use ffmpeg::Rational;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// чем убирать гребёнку с чересстрочных кадров
+#[derive(Clone, Copy, PartialEq)]
+pub enum Method {
+    Yadif,
+    // сложнее yadif и лучше сохраняет детали, но и медленнее
+    Bwdif,
+}
+
+impl Method {
+    // значение --deinterlace, off значит выключен, пока его не включат клавишей D
+    pub fn parse(value: &str) -> Result<Option<Method>> {
+        match value {
+            "yadif" => Ok(Some(Method::Yadif)),
+            "bwdif" => Ok(Some(Method::Bwdif)),
+            "off" => Ok(None),
+            _ => Err(anyhow!(
+                "deinterlace must be yadif, bwdif or off, not {}",
+                value
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::Yadif => "yadif",
+            Method::Bwdif => "bwdif",
+        }
+    }
+}
+
+// включён ли деинтерлейсинг, общий для главного потока и потока видео
+#[derive(Clone)]
+pub struct Switch {
+    method: Method,
+    enabled: Arc<AtomicBool>,
+}
+
+impl Switch {
+    // если метод не задан, то D включает yadif
+    pub fn new(method: Option<Method>) -> Switch {
+        Switch {
+            method: method.unwrap_or(Method::Yadif),
+            enabled: Arc::new(AtomicBool::new(method.is_some())),
+        }
+    }
+
+    // возвращает новое состояние
+    pub fn toggle(&self) -> bool {
+        !self.enabled.fetch_xor(true, Ordering::SeqCst)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    // строка для OSD
+    pub fn describe(&self) -> String {
+        if self.is_enabled() {
+            format!("deinterlace {}", self.method.name())
+        } else {
+            "deinterlace off".to_string()
+        }
+    }
+}
+
+// деинтерлейсер потока видео
+// пока декодер отдаёт прогрессивные кадры, они идут мимо фильтра и без задержки,
+// а с первого чересстрочного кадра все кадры идут через yadif или bwdif:
+// фильтр сам пропускает прогрессивные кадры (deint=interlaced),
+// а порядок полей берёт из каждого кадра (parity=auto)
+pub struct Deinterlacer {
+    switch: Switch,
+    graph: Option<ffmpeg::filter::Graph>,
+    // состояние переключателя, под которое собран graph
+    enabled: bool,
+    // декодер уже отдавал чересстрочные кадры
+    interlaced: bool,
+}
+
+impl Deinterlacer {
+    pub fn new(switch: Switch) -> Deinterlacer {
+        Deinterlacer {
+            switch,
+            graph: None,
+            enabled: false,
+            interlaced: false,
+        }
+    }
+
+    // вызывается между пакетами: если D переключили, то собираем граф заново
+    pub fn update(&mut self, decoder: &ffmpeg::decoder::Video, time_base: Rational) -> Result<()> {
+        if self.switch.is_enabled() != self.enabled {
+            self.reset(decoder, time_base)?;
+        }
+        Ok(())
+    }
+
+    // фильтр держит у себя соседние кадры, после перемотки они уже не соседние,
+    // поэтому граф собирается заново
+    pub fn reset(&mut self, decoder: &ffmpeg::decoder::Video, time_base: Rational) -> Result<()> {
+        self.enabled = self.switch.is_enabled();
+        self.graph = if self.enabled {
+            let spec = format!(
+                "{}=mode=send_frame:parity=auto:deint=interlaced",
+                self.switch.method.name()
+            );
+            Some(filter::video_filter(
+                &spec,
+                decoder,
+                time_base,
+                decoder.format(),
+            )?)
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    // отдаёт в show готовые кадры, фильтр отдаёт кадр с задержкой на один
+    // false если show больше кадров не ждёт
+    pub fn filter<F>(&mut self, frame: &Video, mut show: F) -> Result<bool, ffmpeg::Error>
+    where
+        F: FnMut(&Video) -> Result<bool, ffmpeg::Error>,
+    {
+        self.interlaced |= frame.is_interlaced();
+        match self.graph.as_mut() {
+            Some(graph) if self.interlaced => {
+                graph.get("in").unwrap().source().add(frame)?;
+                receive(graph, show)
+            }
+            _ => show(frame),
+        }
+    }
+
+    // в конце файла отдаёт в show последний кадр, который фильтр держит у себя,
+    // после этого граф новых кадров не берёт и его нужно собрать заново через reset
+    pub fn flush<F>(&mut self, show: F) -> Result<bool, ffmpeg::Error>
+    where
+        F: FnMut(&Video) -> Result<bool, ffmpeg::Error>,
+    {
+        match self.graph.as_mut() {
+            Some(graph) if self.interlaced => {
+                graph.get("in").unwrap().source().flush()?;
+                receive(graph, show)
+            }
+            _ => Ok(true),
+        }
+    }
+}
+
+// забирает с выхода графа всё готовое, false если show больше кадров не ждёт
+fn receive<F>(graph: &mut ffmpeg::filter::Graph, mut show: F) -> Result<bool, ffmpeg::Error>
+where
+    F: FnMut(&Video) -> Result<bool, ffmpeg::Error>,
+{
+    let mut deinterlaced = Video::empty();
+    while graph
+        .get("out")
+        .unwrap()
+        .sink()
+        .frame(&mut deinterlaced)
+        .is_ok()
+    {
+        if !show(&deinterlaced)? {
+            return Ok(false);
+        }
+        deinterlaced = Video::empty();
+    }
+    Ok(true)
+}
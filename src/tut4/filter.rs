@@ -0,0 +1,98 @@
+use anyhow::{anyhow, Context as ErrorContext, Result};
+use ffmpeg::format::{Pixel, Sample};
+use ffmpeg::{filter, ChannelLayout, Rational};
+
+// строим граф фильтров для видео: buffer -> spec -> buffersink
+// на выходе всегда получаем кадры в формате, который умеет рисовать draw_frame
+pub fn video_filter(
+    spec: &str,
+    decoder: &ffmpeg::codec::decoder::Video,
+    time_base: Rational,
+    format: Pixel,
+) -> Result<filter::Graph> {
+    let mut graph = filter::Graph::new();
+
+    let aspect_ratio = match decoder.aspect_ratio() {
+        ratio if ratio.numerator() == 0 => Rational::new(1, 1),
+        ratio => ratio,
+    };
+    let args = format!(
+        "video_size={}x{}:pix_fmt={}:time_base={}:pixel_aspect={}",
+        decoder.width(),
+        decoder.height(),
+        pixel_name(decoder.format())?,
+        time_base,
+        aspect_ratio,
+    );
+
+    graph.add(&find("buffer")?, "in", &args)?;
+    graph.add(&find("buffersink")?, "out", "")?;
+    graph
+        .get("out")
+        .ok_or_else(|| anyhow!("no output in filter graph"))?
+        .set_pixel_format(format);
+
+    // "in" и "out" здесь это имена концов графа, к которым подключается spec
+    graph.output("in", 0)?.input("out", 0)?.parse(spec)?;
+    graph
+        .validate()
+        .with_context(|| format!("invalid video filter graph {}", spec))?;
+
+    Ok(graph)
+}
+
+// то же самое для аудио: abuffer -> spec -> abuffersink
+// выход приводится к формату устройства, поэтому ресемплер после графа не нужен
+pub fn audio_filter(
+    spec: &str,
+    decoder: &ffmpeg::codec::decoder::Audio,
+    time_base: Rational,
+    format: Sample,
+    channel_layout: ChannelLayout,
+    rate: u32,
+) -> Result<filter::Graph> {
+    let mut graph = filter::Graph::new();
+
+    let args = format!(
+        "time_base={}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+        time_base,
+        decoder.rate(),
+        decoder.format().name(),
+        decoder.channel_layout().bits(),
+    );
+
+    graph.add(&find("abuffer")?, "in", &args)?;
+    graph.add(&find("abuffersink")?, "out", "")?;
+    {
+        let mut out = graph
+            .get("out")
+            .ok_or_else(|| anyhow!("no output in filter graph"))?;
+        out.set_sample_format(format);
+        out.set_channel_layout(channel_layout);
+        out.set_sample_rate(rate);
+    }
+
+    graph.output("in", 0)?.input("out", 0)?.parse(spec)?;
+    graph
+        .validate()
+        .with_context(|| format!("invalid audio filter graph {}", spec))?;
+
+    Ok(graph)
+}
+
+// time base кадров на выходе графа, фильтры вроде fps, setpts или aresample его меняют
+pub fn output_time_base(graph: &mut filter::Graph) -> Rational {
+    let out = graph.get("out").unwrap();
+    Rational::from(unsafe { ffmpeg::ffi::av_buffersink_get_time_base(out.as_ptr()) })
+}
+
+fn find(name: &str) -> Result<filter::Filter> {
+    filter::find(name).ok_or_else(|| anyhow!("filter {} not found", name))
+}
+
+fn pixel_name(format: Pixel) -> Result<&'static str> {
+    format
+        .descriptor()
+        .map(|descriptor| descriptor.name())
+        .ok_or_else(|| anyhow!("unknown pixel format {:?}", format))
+}
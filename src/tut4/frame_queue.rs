@@ -0,0 +1,279 @@
+use crate::cancel::CancellationToken;
+use crate::message::{DecodedAudio, DecodedFrame};
+use std::collections::VecDeque;
+use std::sync::mpsc::{RecvError, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+// то что можно класть в очередь: размер нужен для ограничения по памяти
+pub trait QueueItem {
+    fn size(&self) -> usize;
+}
+
+impl QueueItem for DecodedFrame {
+    fn size(&self) -> usize {
+        DecodedFrame::size(self)
+    }
+}
+
+impl QueueItem for DecodedAudio {
+    fn size(&self) -> usize {
+        self.frame.data(0).len()
+    }
+}
+
+// очередь готовых кадров между потоком декодера и рендером
+// ограничена и числом кадров, и их суммарным размером: кадр 4K весит как десяток кадров 480p
+// при отмене конвейера ждущий в send() поток просыпается сразу
+pub fn frame_queue<T: QueueItem + Send + 'static>(
+    max_frames: usize,
+    max_bytes: usize,
+    cancel: &CancellationToken,
+) -> (FrameSender<T>, FrameQueue<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            frames: VecDeque::new(),
+            bytes: 0,
+            finished: false,
+            closed: false,
+        }),
+        changed: Condvar::new(),
+        max_frames,
+        max_bytes,
+        cancel: cancel.clone(),
+    });
+    // слабая ссылка, иначе очередь и токен держат друг друга и не удаляются никогда
+    let waker: Weak<Shared<T>> = Arc::downgrade(&shared);
+    cancel.on_cancel(move || {
+        if let Some(shared) = waker.upgrade() {
+            let _state = shared.state.lock().unwrap();
+            shared.changed.notify_all();
+        }
+    });
+    (
+        FrameSender {
+            shared: shared.clone(),
+        },
+        FrameQueue { shared },
+    )
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    changed: Condvar,
+    max_frames: usize,
+    max_bytes: usize,
+    cancel: CancellationToken,
+}
+
+struct State<T> {
+    frames: VecDeque<T>,
+    bytes: usize,
+    // декодер больше ничего не пришлёт
+    finished: bool,
+    // рендер больше ничего не прочитает
+    closed: bool,
+}
+
+impl<T> Shared<T> {
+    fn is_full(&self, state: &State<T>) -> bool {
+        // пустая очередь принимает кадр любого размера, иначе огромный кадр не пролезет никогда
+        !state.frames.is_empty()
+            && (state.frames.len() >= self.max_frames || state.bytes >= self.max_bytes)
+    }
+}
+
+// сторона декодера, при удалении очередь считается законченной, как у mpsc::Sender
+pub struct FrameSender<T = DecodedFrame> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: QueueItem> FrameSender<T> {
+    // ждёт места в очереди, false если рендер закрыл очередь или конвейер останавливается
+    pub fn send(&self, frame: T) -> bool {
+        let size = frame.size();
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if state.closed || self.shared.cancel.is_cancelled() {
+                return false;
+            }
+            if !self.shared.is_full(&state) {
+                break;
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        }
+        state.bytes += size;
+        state.frames.push_back(frame);
+        self.shared.changed.notify_all();
+        true
+    }
+}
+
+impl<T> Drop for FrameSender<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().finished = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+// сторона рендера, ошибки те же что у mpsc::Receiver:
+// Disconnected значит что декодер закончил и все его кадры уже прочитаны
+pub struct FrameQueue<T = DecodedFrame> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: QueueItem> FrameQueue<T> {
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(frame) = self.take(&mut state) {
+                return Ok(frame);
+            }
+            if state.finished {
+                return Err(RecvError);
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.state.lock().unwrap();
+        match self.take(&mut state) {
+            Some(frame) => Ok(frame),
+            None if state.finished => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(frame) = self.take(&mut state) {
+                return Ok(frame);
+            }
+            if state.finished {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .shared
+                .changed
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    fn take(&self, state: &mut State<T>) -> Option<T> {
+        let frame = state.frames.pop_front()?;
+        state.bytes -= frame.size();
+        self.shared.changed.notify_all();
+        Some(frame)
+    }
+}
+
+impl<T> Drop for FrameQueue<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.closed = true;
+        state.frames.clear();
+        state.bytes = 0;
+        self.shared.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::thread;
+
+    const WAIT: Duration = Duration::from_secs(5);
+    // за это время заблокированный поток точно успел бы пройти дальше
+    const BLOCKED: Duration = Duration::from_millis(50);
+
+    struct Item(usize);
+
+    impl QueueItem for Item {
+        fn size(&self) -> usize {
+            self.0
+        }
+    }
+
+    // отправляет item из другого потока, результат send() приходит в канал
+    fn send_later(sender: FrameSender<Item>, item: Item) -> Receiver<bool> {
+        let (done_tx, done_rx) = channel();
+        thread::spawn(move || done_tx.send(sender.send(item)).unwrap());
+        done_rx
+    }
+
+    #[test]
+    fn blocked_send_wakes_on_recv() {
+        let cancel = CancellationToken::new();
+        let (sender, queue) = frame_queue(1, usize::MAX, &cancel);
+        assert!(sender.send(Item(1)));
+
+        let done = send_later(sender, Item(2));
+        assert!(done.recv_timeout(BLOCKED).is_err());
+        assert_eq!(queue.recv().unwrap().0, 1);
+        assert_eq!(done.recv_timeout(WAIT), Ok(true));
+        assert_eq!(queue.recv().unwrap().0, 2);
+        // декодер закончил и всё прочитано
+        assert_eq!(queue.recv().err(), Some(RecvError));
+    }
+
+    #[test]
+    fn byte_bound_blocks_send() {
+        let cancel = CancellationToken::new();
+        let (sender, queue) = frame_queue(10, 100, &cancel);
+        // пустая очередь берёт и кадр больше ограничения
+        assert!(sender.send(Item(1000)));
+        let done = send_later(sender, Item(10));
+        assert!(done.recv_timeout(BLOCKED).is_err());
+
+        assert_eq!(queue.try_recv().unwrap().0, 1000);
+        assert_eq!(done.recv_timeout(WAIT), Ok(true));
+        assert_eq!(queue.try_recv().unwrap().0, 10);
+    }
+
+    #[test]
+    fn cancel_wakes_blocked_send() {
+        let cancel = CancellationToken::new();
+        let (sender, _queue) = frame_queue(1, usize::MAX, &cancel);
+        assert!(sender.send(Item(1)));
+
+        let done = send_later(sender, Item(2));
+        assert!(done.recv_timeout(BLOCKED).is_err());
+        cancel.cancel();
+        assert_eq!(done.recv_timeout(WAIT), Ok(false));
+    }
+
+    #[test]
+    fn closed_queue_refuses_frames() {
+        let cancel = CancellationToken::new();
+        let (sender, queue) = frame_queue(1, usize::MAX, &cancel);
+        assert!(sender.send(Item(1)));
+        let done = send_later(sender, Item(2));
+        drop(queue);
+        assert_eq!(done.recv_timeout(WAIT), Ok(false));
+    }
+
+    #[test]
+    fn recv_timeout_waits_for_frame() {
+        let cancel = CancellationToken::new();
+        let (sender, queue) = frame_queue::<Item>(1, usize::MAX, &cancel);
+        assert_eq!(
+            queue.recv_timeout(BLOCKED).err(),
+            Some(RecvTimeoutError::Timeout)
+        );
+        drop(sender);
+        assert_eq!(
+            queue.recv_timeout(BLOCKED).err(),
+            Some(RecvTimeoutError::Disconnected)
+        );
+    }
+}
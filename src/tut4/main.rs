@@ -1,256 +1,1256 @@
 extern crate ffmpeg_next as ffmpeg;
 
 use anyhow::{anyhow, Context as ErrorContext, Result};
+use ffmpeg::format::stream::Disposition;
 use ffmpeg::format::{input, sample::Type as AudioType, Pixel, Sample};
 use ffmpeg::frame::Audio;
 use ffmpeg::media::Type;
+use ffmpeg::rescale;
 use ffmpeg::software::resampling::{context::Context as AudioContext, flag::Flags as AudioFlags};
-use ffmpeg::software::scaling::{context::Context, flag::Flags};
 use ffmpeg::util::frame::video::Video;
+use fftut::decode::{drain, start_time, DecodeErrors, ErrorPolicy};
+use fftut::playlist::Playlist;
+use fftut::rotation::Rotation;
+use fftut::scale::Scaling;
+use fftut::snapshot::{frame_ref, save_rotated_screenshot};
 use sdl2::audio::{AudioSpec, AudioSpecDesired};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::PixelFormatEnum;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
 use sdl2::render::{TextureCreator, WindowCanvas};
 use sdl2::video::WindowContext;
 use std::env;
 
+mod bench;
+mod cancel;
+mod chapters;
+mod deinterlace;
+mod filter;
+mod frame_queue;
+mod message;
+mod options;
+mod osd;
+mod packet_queue;
+mod progress;
+mod sink;
+mod visualizer;
+mod worker;
+
+use cancel::CancellationToken;
+use deinterlace::{Deinterlacer, Switch};
+use frame_queue::{frame_queue, FrameQueue, FrameSender};
+use message::{Command, DecodedAudio, DecodedFrame, PacketMessage, Worker, WorkerEvent};
+use options::Options;
+use osd::Osd;
+use packet_queue::{packet_queue, PacketQueue, PacketReceiver};
+use progress::Progress;
+use sink::{AudioSink, NullAudio};
+use visualizer::Visualizer;
+use worker::{spawn_worker, WorkerThread};
+
+const WINDOW_TITLE: &str = "rust-sdl2 demo: Video";
+// сколько ждём остановки потоков, прежде чем бросить их
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+// сколько готовых кадров может ждать показа: не больше 8 штук и не больше 64 МБ
+const FRAME_QUEUE_FRAMES: usize = 8;
+const FRAME_QUEUE_BYTES: usize = 64 << 20;
+// больше стольких байт в очередях пакетов поток чтения не держит, даже если какой-то очереди мало
+const PACKET_QUEUE_BYTES: usize = 15 << 20;
+
+// сколько звука держим в очереди звуковой карты, остальное ждёт в FrameQueue
+// и через неё притормаживает аудио декодер и поток чтения
+const AUDIO_QUEUE_SECONDS: f64 = 0.5;
+
+// размер окна, если первый файл плейлиста без видео
+const VISUALIZER_SIZE: (u32, u32) = (640, 360);
+
 struct RenderEvent {
     a: u32,
 }
 
-#[allow(clippy::too_many_lines)]
+// чем закончилось проигрывание одного элемента плейлиста
+enum Transition {
+    Next,
+    Previous,
+    Quit,
+    // с --strict ошибка любого потока останавливает весь плейлист
+    Abort(anyhow::Error),
+}
+
+// видео поток файла, у музыки его нет
+struct VideoInput {
+    decoder: ffmpeg::codec::decoder::Video,
+    index: usize,
+    time_base: ffmpeg::Rational,
+    // поворот из метаданных, кадры поворачиваются в потоке видео
+    rotation: Rotation,
+}
+
+// окно и аудио устройство создаются по первому файлу и переходят от файла к файлу,
+// поэтому между элементами плейлиста нет паузы на их пересоздание
+struct Output {
+    canvas: WindowCanvas,
+    texture_creator: TextureCreator<WindowContext>,
+    audio_device: AudioSink,
+    // сообщения поверх видео, ошибка прошлого файла видна и в начале следующего
+    osd: Osd,
+}
+
 fn main() -> Result<()> {
     // регистриует все доступные форматы, кодеки и т.д.
     ffmpeg::init().unwrap();
 
-    // открываем указанный input сюда идёт всё то что можно указать через -i
-    // по сути читает header файла или подобные действия получает информацию о формате input
-    if let Ok(mut ictx) = input(
-        &env::args()
-            .nth(1)
-            .ok_or_else(|| anyhow!("no input specified"))?,
-    ) {
+    let options = Options::parse()?;
+    let mut playlist = Playlist::new(&options.inputs)?;
+
+    // с --bench SDL вообще не нужен
+    if options.bench {
+        loop {
+            if let Err(e) = bench::run(playlist.current(), &options) {
+                eprintln!("{}: {:#}", playlist.current(), e);
+            }
+            if !playlist.next() {
+                bench::report_peak_memory();
+                return Ok(());
+            }
+        }
+    }
+
+    // по сути какой-то синглтон который следит за тем что бы у нас не было несколько контекстов
+    // без дисплея окно создаётся в dummy драйвере SDL: всё рисуется, но никуда не выводится
+    // звук всё равно идёт в NullAudio, но аудио подсистема открывается и без звуковой карты
+    // падает, поэтому и у неё dummy драйвер
+    if options.headless {
+        env::set_var("SDL_VIDEODRIVER", "dummy");
+        env::set_var("SDL_AUDIODRIVER", "dummy");
+    }
+    let sdl_context = sdl2::init().map_err(|e| anyhow!(e))?;
+    // кадры переводятся в YUV по BT.601 (см. Scaler), а SDL по умолчанию у текстур
+    // выше 576 строк считает YUV в BT.709, и у HD видео едут оттенки
+    sdl2::hint::set("SDL_YUV_CONVERSION_MODE", "BT601");
+    // по сути это SDL_init(SDL_INIT_VIDEO)
+    let video_subsystem = sdl_context.video().map_err(|e| anyhow!(e))?;
+    let audio_subsystem = sdl_context.audio().map_err(|e| anyhow!(e))?;
+    let mut event_pump = sdl_context.event_pump().map_err(|e| anyhow!(e))?;
+
+    let event_subsystem = sdl_context.event().map_err(|e| anyhow!(e))?;
+    event_subsystem
+        .register_custom_event::<RenderEvent>()
+        .unwrap();
+    let timer_subsystem = sdl_context.timer().map_err(|e| anyhow!(e))?;
+
+    let mut output: Option<Output> = None;
+    // потоки прошлых файлов, которые не остановились за SHUTDOWN_TIMEOUT
+    let mut stopping: Vec<WorkerThread> = Vec::new();
+
+    loop {
+        let path = playlist.current().to_string();
+
+        // открываем указанный input сюда идёт всё то что можно указать через -i
+        // по сути читает header файла или подобные действия получает информацию о формате input
+        let ictx = match input(&path) {
+            Ok(ictx) => ictx,
+            Err(e) => {
+                // битый элемент плейлиста не повод останавливать весь плейлист
+                eprintln!("couldn't open {}: {}", path, e);
+                if playlist.next() {
+                    continue;
+                }
+                break;
+            }
+        };
         // дамп информации о контексте input'а, тертий параметр не обязательный
-        ffmpeg::format::context::input::dump(&ictx, 0, Some(env::args().nth(1).unwrap().as_str()));
-
-        // даелее мы смотрим доступные потоки, конкретно тут мы ищем "лучший" видео поток
-        let video_input = ictx
-            .streams()
-            .best(Type::Video)
-            .ok_or(ffmpeg::Error::StreamNotFound)?;
-        // дальше находим лучший аудио поток
-        let audio_input = ictx
-            .streams()
-            .best(Type::Audio)
-            .ok_or(ffmpeg::Error::StreamNotFound)?;
-
-        let video_stream_index = video_input.index();
-        let audio_stream_index = audio_input.index();
-
-        // находим декодер (кодек) по id видео потока
-        // под копотом в функции .video() вызывает avcodec_find_decoder()
-        // и потом открывается сам коде через avcodec_open2()
-        let video_decoder = video_input.codec().decoder().video()?;
-
-        // находим так же и кодек аудио
-        let audio_decoder = audio_input.codec().decoder().audio()?;
-
-        // по сути какой-то синглтон который следит за тем что бы у нас не было несколько контекстов
-        let sdl_context = sdl2::init().map_err(|e| anyhow!(e))?;
-        // по сути это SDL_init(SDL_INIT_VIDEO)
-        let video_subsystem = sdl_context.video().map_err(|e| anyhow!(e))?;
-        let audio_subsystem = sdl_context.audio().map_err(|e| anyhow!(e))?;
+        ffmpeg::format::context::input::dump(&ictx, 0, Some(path.as_str()));
+
+        let transition = match play(
+            ictx,
+            &path,
+            &options,
+            &video_subsystem,
+            &audio_subsystem,
+            &mut output,
+            &mut stopping,
+            &mut event_pump,
+            &event_subsystem,
+            &timer_subsystem,
+        ) {
+            Ok(transition) => transition,
+            // файл открылся, но проиграть его не вышло (нет декодера и т.п.), идём дальше
+            Err(e) => {
+                eprintln!("{}: {:#}", path, e);
+                Transition::Next
+            }
+        };
+
+        match transition {
+            Transition::Next => {
+                if !playlist.next() {
+                    // плейлист закончился сам, даём звуковой карте доиграть очередь
+                    if let Some(output) = &output {
+                        output.audio_device.wait_until_played();
+                    }
+                    break;
+                }
+            }
+            Transition::Previous => playlist.previous(),
+            Transition::Quit => break,
+            Transition::Abort(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_lines, clippy::too_many_arguments)]
+fn play(
+    ictx: ffmpeg::format::context::Input,
+    path: &str,
+    options: &Options,
+    video_subsystem: &sdl2::VideoSubsystem,
+    audio_subsystem: &sdl2::AudioSubsystem,
+    output: &mut Option<Output>,
+    stopping: &mut Vec<WorkerThread>,
+    event_pump: &mut sdl2::EventPump,
+    event_subsystem: &sdl2::EventSubsystem,
+    timer_subsystem: &sdl2::TimerSubsystem,
+) -> Result<Transition> {
+    // даелее мы смотрим доступные потоки, конкретно тут мы ищем "лучший" видео поток
+    // обложка альбома в mp3 тоже видео поток, но из одного кадра, такой файл считаем музыкой
+    let video = match ictx.streams().best(Type::Video) {
+        Some(video_input)
+            if !video_input
+                .disposition()
+                .contains(Disposition::ATTACHED_PIC) =>
+        {
+            Some(VideoInput {
+                // находим декодер (кодек) по id видео потока
+                // под копотом в функции .video() вызывает avcodec_find_decoder()
+                // и потом открывается сам коде через avcodec_open2()
+                decoder: options.decoder.video(&video_input)?,
+                index: video_input.index(),
+                time_base: video_input.time_base(),
+                rotation: Rotation::of(&video_input),
+            })
+        }
+        _ => None,
+    };
+    // дальше находим лучший аудио поток
+    let audio_input = ictx
+        .streams()
+        .best(Type::Audio)
+        .ok_or(ffmpeg::Error::StreamNotFound)?;
+
+    let audio_stream_index = audio_input.index();
+    let audio_time_base = audio_input.time_base();
+    // кадры сразу переводятся в размер окна, а окно не больше экрана,
+    // так что 4K на маленьком экране не гоняет в видеокарту 4K текстуры
+    let bounds = video_subsystem
+        .display_usable_bounds(0)
+        .map(|bounds| bounds.size())
+        .unwrap_or((u32::MAX, u32::MAX));
+    // у повёрнутого на 90 градусов видео ширина и высота окна меняются местами
+    let rotated = video.as_ref().map(|video| {
+        video
+            .rotation
+            .size(video.decoder.width(), video.decoder.height())
+    });
+    let scaling = match rotated {
+        Some((width, height)) => options.scaling.fit(width, height, bounds),
+        None => options.scaling,
+    };
+    // без видео окно показывает осциллограмму и спектр, размер окна тогда не важен
+    let size = match rotated {
+        Some((width, height)) => scaling.size(width, height),
+        None => canvas_size(output).unwrap_or(VISUALIZER_SIZE),
+    };
 
+    // находим так же и кодек аудио
+    let audio_decoder = options.decoder.audio(&audio_input)?;
+
+    if output.is_none() {
         // создаём окно в котором будем отображать информацию
-        let window = video_subsystem
-            .window(
-                "rust-sdl2 demo: Video",
-                video_decoder.width(),
-                video_decoder.height(),
-            )
-            .position_centered()
-            .opengl()
-            .build()
-            .context("couldn't create window")?;
+        let mut window = video_subsystem.window(WINDOW_TITLE, size.0, size.1);
+        window.position_centered();
+        // у dummy драйвера нет OpenGL, там канвас рисует программно
+        if !options.headless {
+            window.opengl();
+        }
+        let window = window.build().context("couldn't create window")?;
 
         // создаём канвас в окне SDL_CreateRenderer()
-        let mut canvas = window
-            .into_canvas()
-            .build()
-            .context("couldn't create canvas")?;
+        let mut canvas = window.into_canvas();
+        if options.headless {
+            canvas = canvas.software();
+        }
+        let canvas = canvas.build().context("couldn't create canvas")?;
         let texture_creator = canvas.texture_creator();
 
-        let desired_spec = AudioSpecDesired {
-            freq: Some(audio_decoder.rate() as i32),
-            channels: Some(audio_decoder.channels() as u8),
-            samples: Some(4),
+        // без звуковой карты звук "играется" в никуда, но с настоящей скоростью
+        let audio_device = if options.headless {
+            AudioSink::Null(NullAudio::new(
+                audio_decoder.rate() as i32,
+                audio_decoder.channels() as u8,
+            ))
+        } else {
+            let desired_spec = AudioSpecDesired {
+                freq: Some(audio_decoder.rate() as i32),
+                channels: Some(audio_decoder.channels() as u8),
+                samples: Some(4),
+            };
+
+            AudioSink::Device(
+                audio_subsystem
+                    .open_queue::<i16, _>(None, &desired_spec)
+                    .map_err(|e| anyhow!(e))?,
+            )
         };
 
-        let audio_device = audio_subsystem
-            .open_queue::<i16, _>(None, &desired_spec)
-            .map_err(|e| anyhow!(e))?;
+        *output = Some(Output {
+            canvas,
+            texture_creator,
+            audio_device,
+            osd: Osd::new(),
+        });
+    }
+    let Output {
+        canvas,
+        texture_creator,
+        audio_device,
+        osd,
+    } = output.as_mut().unwrap();
 
-        let audio_started = false;
+    // окно остаётся от прошлого файла, подгоняем его под размер нового видео
+    if canvas.window().size() != size {
+        canvas
+            .window_mut()
+            .set_size(size.0, size.1)
+            .context("couldn't resize window")?;
+    }
 
-        let break_flag = std::sync::Arc::new(std::sync::Mutex::new(false));
+    let mut audio_started = false;
+    // на паузе кадры не показываются сами, их можно листать клавишами "." и ","
+    let mut paused = false;
+    // номер текущей серии кадров, увеличивается при каждой перемотке
+    let mut serial = 0;
+    // A-B повтор: первое нажатие R ставит начало, второе конец, третье выключает повтор
+    let mut repeat_start: Option<f64> = None;
+    let mut repeat_end: Option<f64> = None;
+    // A-B повтор уже перемотал в начало отрезка, но кадров новой серии ещё не было
+    // до них позиция остаётся старой, за концом отрезка, и перематывать снова не нужно
+    let mut pending_seek = false;
 
-        let mut event_pump = sdl_context.event_pump().map_err(|e| anyhow!(e))?;
+    // потоки декодирования закончились и их очереди вычитаны до конца
+    let mut video_done = false;
+    let mut audio_done = false;
 
-        let (video_decoded_tx, video_decoded_rx) = std::sync::mpsc::sync_channel(8);
-        let (audio_decoded_tx, audio_decoded_rx) = std::sync::mpsc::sync_channel(8);
+    // главы и длительность нужно забрать до того как ictx уйдёт в поток чтения
+    let progress = Progress::new(&ictx);
+    // переключатель деинтерлейсинга для клавиши D, поток видео смотрит на него между пакетами
+    let deinterlace = Switch::new(options.deinterlace);
+    // глава, название которой последним показывали в OSD
+    let mut current_chapter = None;
+    // время последних отданных звуковой карте сэмплов, по нему считается позиция без видео
+    let mut audio_pts: Option<f64> = None;
+    let has_video = video.is_some();
+    // скриншот делается из кадра до поворота, поэтому поворот нужен и здесь
+    let rotation = video
+        .as_ref()
+        .map_or_else(Rotation::default, |video| video.rotation.clone());
+    let mut visualizer = if has_video {
+        None
+    } else {
+        Some(Visualizer::new(audio_device.spec(), AUDIO_QUEUE_SECONDS))
+    };
 
-        let ph = packet_receiver(
-            video_decoder,
-            audio_decoder,
-            video_decoded_tx,
-            audio_decoded_tx,
-            ictx,
-            video_stream_index,
-            audio_stream_index,
-            break_flag.clone(),
-        );
+    // с --headless в конце файла печатаем сколько кадров показано и за сколько
+    let started = std::time::Instant::now();
+    let mut frames_shown = 0;
 
-        draw_frame(&mut video_decoded_rx.recv()?, &mut canvas, &texture_creator)?;
-
-        let event_subsystem = sdl_context.event().map_err(|e| anyhow!(e))?;
-        event_subsystem
-            .register_custom_event::<RenderEvent>()
-            .unwrap();
-
-        let timer_subsystem = sdl_context.timer().map_err(|e| anyhow!(e))?;
-        let mut timer = timer_subsystem.add_timer(
-            31,
-            Box::new(|| {
-                event_subsystem
-                    .push_custom_event(RenderEvent { a: 42 })
-                    .unwrap();
-                0
-            }),
-        );
+    let cancel = CancellationToken::new();
 
-        loop {
-            if let Ok(frame_to_play) = audio_decoded_rx.try_recv() {
-                audio_device.queue(unsafe { frame_to_play.data(0).align_to::<i16>() }.1);
-                if !audio_started {
+    let (video_decoded_tx, video_decoded_rx) =
+        frame_queue(FRAME_QUEUE_FRAMES, FRAME_QUEUE_BYTES, &cancel);
+    let (audio_decoded_tx, audio_decoded_rx) = frame_queue(8, usize::MAX, &cancel);
+    let (command_tx, command_rx) = std::sync::mpsc::channel();
+    let (events_tx, events_rx) = std::sync::mpsc::channel();
+
+    let ph = packet_receiver(
+        video,
+        audio_decoder,
+        video_decoded_tx,
+        audio_decoded_tx,
+        ictx,
+        audio_stream_index,
+        audio_time_base,
+        options.video_filter.clone(),
+        options.scaling,
+        bounds,
+        deinterlace.clone(),
+        options.audio_filter.clone(),
+        // все файлы плейлиста приводятся к формату уже открытого устройства
+        *audio_device.spec(),
+        options.looping,
+        options.error_policy,
+        command_rx,
+        events_tx,
+        cancel.clone(),
+    );
+
+    // последний показанный кадр, без видео его нет
+    let mut current_frame = match video_decoded_rx.recv() {
+        Ok(mut frame) => {
+            // с --vf размер кадра становится известен только на выходе графа
+            let frame_size = (frame.frame.width(), frame.frame.height());
+            if canvas.window().size() != frame_size {
+                canvas
+                    .window_mut()
+                    .set_size(frame_size.0, frame_size.1)
+                    .context("couldn't resize window")?;
+            }
+            draw_frame(&mut frame, canvas, texture_creator, &progress, osd)?;
+            Some(frame)
+        }
+        Err(_) if !has_video => None,
+        // ни одного кадра так и не пришло, причину расскажут события потоков
+        Err(_) => {
+            drop(video_decoded_rx);
+            drop(audio_decoded_rx);
+            return Ok(stop_pipeline(
+                ph,
+                stopping,
+                &cancel,
+                &events_rx,
+                options.error_policy,
+                osd,
+                Transition::Next,
+            ));
+        }
+    };
+
+    let mut timer = timer_subsystem.add_timer(
+        31,
+        Box::new(|| {
+            event_subsystem
+                .push_custom_event(RenderEvent { a: 42 })
+                .unwrap();
+            0
+        }),
+    );
+
+    let transition = 'playback: loop {
+        // OSD поменялся, и на паузе кадр надо перерисовать, иначе сообщение не появится
+        // или не пропадёт
+        let mut redraw = osd.expire();
+        while let Ok(event) = events_rx.try_recv() {
+            redraw = true;
+            if let Some(error) = report(event, osd) {
+                break 'playback match options.error_policy {
+                    ErrorPolicy::Abort => Transition::Abort(error),
+                    ErrorPolicy::Skip => Transition::Next,
+                };
+            }
+        }
+
+        // звуковой карте хватает звука, новый кадр заберём, когда она часть проиграет
+        let audio_received = if audio_queue_full(audio_device) {
+            Err(std::sync::mpsc::TryRecvError::Empty)
+        } else {
+            audio_decoded_rx.try_recv()
+        };
+        match audio_received {
+            Ok(frame_to_play) => {
+                if frame_to_play.serial == serial {
+                    let samples = frame_to_play.samples(audio_device.spec().channels);
+                    audio_device.queue(samples);
+                    if let Some(visualizer) = visualizer.as_mut() {
+                        visualizer.push(samples);
+                    }
+                    audio_pts = frame_to_play.pts;
+                    // без видео позицию показывает звук
+                    if !has_video {
+                        pending_seek = false;
+                    }
+                }
+                if !audio_started && !paused {
                     audio_device.resume();
+                    audio_started = true;
                 }
             }
-            match event_pump.poll_event() {
-                Some(event) if event.is_user_event() => {
-                    if let Ok(mut frame_to_display) = video_decoded_rx.try_recv() {
-                        draw_frame(&mut frame_to_display, &mut canvas, &texture_creator).unwrap();
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => audio_done = true,
+            Err(std::sync::mpsc::TryRecvError::Empty) => {}
+        }
+        // файл проигран только когда и видео и аудио поток отдали всё до последнего кадра
+        if video_done && audio_done {
+            break Transition::Next;
+        }
+
+        match event_pump.poll_event() {
+            Some(event) if event.is_user_event() => {
+                if !paused {
+                    match video_decoded_rx.try_recv() {
+                        Ok(mut frame_to_display) => {
+                            if frame_to_display.serial == serial {
+                                draw_frame(
+                                    &mut frame_to_display,
+                                    canvas,
+                                    texture_creator,
+                                    &progress,
+                                    osd,
+                                )?;
+                                current_frame = Some(frame_to_display);
+                                frames_shown += 1;
+                                pending_seek = false;
+                            }
+                        }
+                        // видео закончилось, но аудио ещё может остаться в очереди
+                        // аудио что уже в устройстве доиграет вместе с началом следующего файла
+                        Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                            video_done = true;
+                        }
+                        Err(std::sync::mpsc::TryRecvError::Empty) => {}
+                    }
+
+                    let position =
+                        playback_position(&current_frame, serial, audio_pts, audio_device);
+                    // без видео рисуем то, что сейчас играет звуковая карта
+                    if let Some(visualizer) = &visualizer {
+                        let spec = audio_device.spec();
+                        let queued = audio_device.size() as usize / 2 / usize::from(spec.channels);
+                        visualizer.draw(canvas, queued)?;
+                        progress.draw(canvas, position)?;
+                        osd.draw(canvas)?;
+                        canvas.present();
                     }
 
-                    timer = timer_subsystem.add_timer(
-                        31,
-                        Box::new(|| {
-                            event_subsystem
-                                .push_custom_event(RenderEvent { a: 42 })
-                                .unwrap();
-                            0
-                        }),
-                    );
+                    // началась новая глава, показываем её название
+                    let chapter =
+                        position.and_then(|position| progress.chapters.index_at(position));
+                    if chapter != current_chapter {
+                        current_chapter = chapter;
+                        if let Some(index) = chapter {
+                            osd.show(progress.chapters.describe(index));
+                        }
+                    }
+
+                    // дошли до конца отрезка A-B, возвращаемся в его начало
+                    if let (Some(start), Some(end), Some(position)) =
+                        (repeat_start, repeat_end, position)
+                    {
+                        if position >= end && !pending_seek {
+                            pending_seek = true;
+                            serial += 1;
+                            audio_pts = None;
+                            command_tx.send(Command::Seek {
+                                seconds: start,
+                                serial,
+                            })?;
+                            audio_device.clear();
+                            if let Some(visualizer) = visualizer.as_mut() {
+                                visualizer.clear();
+                            }
+                        }
+                    }
                 }
-                Some(Event::Quit { .. })
-                | Some(Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                }) => {
-                    break_flag.lock().map(|_| true);
-                    ph.join();
-                    break;
+
+                timer = timer_subsystem.add_timer(
+                    31,
+                    Box::new(|| {
+                        event_subsystem
+                            .push_custom_event(RenderEvent { a: 42 })
+                            .unwrap();
+                        0
+                    }),
+                );
+            }
+            Some(Event::Quit { .. })
+            | Some(Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            }) => break Transition::Quit,
+            // следующий и предыдущий файл плейлиста
+            Some(Event::KeyDown {
+                keycode: Some(Keycode::N),
+                ..
+            }) => {
+                audio_device.clear();
+                break Transition::Next;
+            }
+            Some(Event::KeyDown {
+                keycode: Some(Keycode::P),
+                ..
+            }) => {
+                audio_device.clear();
+                break Transition::Previous;
+            }
+            Some(Event::KeyDown {
+                keycode: Some(Keycode::Space),
+                ..
+            }) => {
+                paused = !paused;
+                if paused {
+                    audio_device.pause();
+                    match &current_frame {
+                        Some(frame) => osd.pin(frame.describe()),
+                        None => osd.pin("paused"),
+                    }
+                    redraw = true;
+                } else {
+                    audio_device.resume();
+                    osd.hide();
+                }
+            }
+            // шаг на один кадр вперёд, просто берём следующий декодированный кадр
+            Some(Event::KeyDown {
+                keycode: Some(Keycode::Period),
+                ..
+            }) if paused => {
+                if let Some(mut frame) =
+                    next_frame(&video_decoded_rx, &audio_decoded_rx, audio_device, serial)
+                {
+                    osd.pin(frame.describe());
+                    draw_frame(&mut frame, canvas, texture_creator, &progress, osd)?;
+                    current_frame = Some(frame);
+                }
+            }
+            // шаг на один кадр назад: перематываем на ключевой кадр перед предыдущим кадром
+            // и декодируем вперёд, пока не дойдём до него
+            Some(Event::KeyDown {
+                keycode: Some(Keycode::Comma),
+                ..
+            }) if paused => {
+                let previous = current_frame
+                    .as_ref()
+                    .and_then(|frame| frame.pts.map(|pts| pts - frame.duration));
+                if let Some(target) = previous {
+                    serial += 1;
+                    audio_pts = None;
+                    command_tx.send(Command::Seek {
+                        seconds: target,
+                        serial,
+                    })?;
+                    audio_device.clear();
+
+                    if let Some(mut frame) =
+                        next_frame(&video_decoded_rx, &audio_decoded_rx, audio_device, serial)
+                    {
+                        osd.pin(frame.describe());
+                        draw_frame(&mut frame, canvas, texture_creator, &progress, osd)?;
+                        current_frame = Some(frame);
+                    }
+                }
+            }
+            // переход на следующую и предыдущую главу
+            Some(Event::KeyDown {
+                keycode: Some(keycode @ Keycode::PageDown),
+                ..
+            })
+            | Some(Event::KeyDown {
+                keycode: Some(keycode @ Keycode::PageUp),
+                ..
+            }) => {
+                let position = playback_position(&current_frame, serial, audio_pts, audio_device)
+                    .unwrap_or(0.0);
+                let chapter = if keycode == Keycode::PageDown {
+                    progress.chapters.next(position)
+                } else {
+                    progress.chapters.previous(position)
+                };
+                if let Some(index) = chapter {
+                    serial += 1;
+                    audio_pts = None;
+                    command_tx.send(Command::Seek {
+                        seconds: progress.chapters.start(index),
+                        serial,
+                    })?;
+                    audio_device.clear();
+                    if let Some(visualizer) = visualizer.as_mut() {
+                        visualizer.clear();
+                    }
+                    current_chapter = Some(index);
+                    osd.show(progress.chapters.describe(index));
+                    redraw = true;
+
+                    // на паузе кадры сами не сменятся, поэтому первый кадр главы показываем сразу
+                    if paused {
+                        if let Some(mut frame) =
+                            next_frame(&video_decoded_rx, &audio_decoded_rx, audio_device, serial)
+                        {
+                            draw_frame(&mut frame, canvas, texture_creator, &progress, osd)?;
+                            current_frame = Some(frame);
+                        }
+                    }
                 }
-                _ => {}
+            }
+            // скриншот показанного кадра в исходном разрешении
+            Some(Event::KeyDown {
+                keycode: Some(Keycode::S),
+                ..
+            }) => {
+                // не получилось сохранить (нет места, нет прав) не повод останавливать видео
+                if let Some(frame) = &current_frame {
+                    let seconds = frame.pts.unwrap_or(0.0);
+                    // у кадров из конца графа фильтров исходного нет, сохраняем показанный
+                    let saved = match &frame.source {
+                        Some(source) => save_rotated_screenshot(source, &rotation, path, seconds),
+                        None => save_rotated_screenshot(
+                            &frame.frame,
+                            &Rotation::default(),
+                            path,
+                            seconds,
+                        ),
+                    };
+                    let status = match saved {
+                        Ok(screenshot) => format!("saved {}", screenshot.display()),
+                        Err(e) => {
+                            eprintln!("couldn't save screenshot: {:#}", e);
+                            "couldn't save screenshot".to_string()
+                        }
+                    };
+                    osd.show(status);
+                    redraw = true;
+                }
+            }
+            // деинтерлейсинг включается и выключается на лету, видно со следующего кадра
+            Some(Event::KeyDown {
+                keycode: Some(Keycode::D),
+                ..
+            }) if has_video => {
+                deinterlace.toggle();
+                osd.show(deinterlace.describe());
+                redraw = true;
+            }
+            Some(Event::KeyDown {
+                keycode: Some(Keycode::R),
+                ..
+            }) => {
+                let position = playback_position(&current_frame, serial, audio_pts, audio_device);
+                match (repeat_start, repeat_end) {
+                    (None, _) => repeat_start = position,
+                    // конец отрезка должен быть после начала, иначе повторять нечего
+                    (Some(start), None) => {
+                        repeat_end = position.filter(|&position| position > start)
+                    }
+                    (Some(_), Some(_)) => {
+                        repeat_start = None;
+                        repeat_end = None;
+                    }
+                }
+                let status = match (repeat_start, repeat_end) {
+                    (Some(start), Some(end)) => {
+                        format!("A-B repeat {:.3}s - {:.3}s", start, end)
+                    }
+                    (Some(start), None) => format!("A-B repeat from {:.3}s", start),
+                    _ => "A-B repeat off".to_string(),
+                };
+                osd.show(status);
+                redraw = true;
+            }
+            _ => {}
+        }
+
+        if paused && redraw {
+            if let Some(frame) = current_frame.as_mut() {
+                draw_frame(frame, canvas, texture_creator, &progress, osd)?;
             }
         }
+    };
+
+    if options.headless {
+        let elapsed = started.elapsed().as_secs_f64();
+        eprintln!(
+            "{}: {} frames shown in {:.2}s ({:.1} fps)",
+            path,
+            frames_shown,
+            elapsed,
+            f64::from(frames_shown) / elapsed
+        );
     }
 
-    Ok(())
+    // очереди закрываем, чтобы никто не ждал места в очереди, которую больше никто не читает
+    drop(video_decoded_rx);
+    drop(audio_decoded_rx);
+    Ok(stop_pipeline(
+        ph,
+        stopping,
+        &cancel,
+        &events_rx,
+        options.error_policy,
+        osd,
+        transition,
+    ))
+}
+
+// останавливаем все потоки конвейера и разбираем события, которые они успели прислать
+// если при --strict какой-то поток упал, проигрывание заканчивается его ошибкой
+#[allow(clippy::too_many_arguments)]
+fn stop_pipeline(
+    ph: WorkerThread,
+    stopping: &mut Vec<WorkerThread>,
+    cancel: &CancellationToken,
+    events_rx: &std::sync::mpsc::Receiver<WorkerEvent>,
+    policy: ErrorPolicy,
+    osd: &mut Osd,
+    mut transition: Transition,
+) -> Transition {
+    cancel.cancel();
+    // зависшие раньше потоки, которые всё-таки закончились, наконец дожидаемся
+    let (finished, hung): (Vec<_>, Vec<_>) =
+        stopping.drain(..).partition(WorkerThread::is_finished);
+    *stopping = hung;
+    for thread in finished {
+        thread.join().unwrap_or(());
+    }
+    match ph.join_timeout(SHUTDOWN_TIMEOUT) {
+        Ok(Ok(())) => {}
+        Ok(Err(_)) => eprintln!("demux thread panicked"),
+        // зависший поток не держит плейлист, но и не бросается: его дождёмся позже
+        Err(ph) => {
+            eprintln!("pipeline didn't stop in {:?}", SHUTDOWN_TIMEOUT);
+            stopping.push(ph);
+        }
+    }
+
+    for event in events_rx.try_iter() {
+        if let Some(error) = report(event, osd) {
+            if policy == ErrorPolicy::Abort && !matches!(transition, Transition::Abort(_)) {
+                transition = Transition::Abort(error);
+            }
+        }
+    }
+
+    transition
+}
+
+// печатаем событие конвейера и показываем его поверх видео
+// для упавшего потока возвращаем его ошибку
+fn report(event: WorkerEvent, osd: &mut Osd) -> Option<anyhow::Error> {
+    eprintln!("{}", event);
+    osd.show(event.to_string());
+    match event {
+        WorkerEvent::Failed { worker, error } => {
+            Some(error.context(format!("{} thread failed", worker)))
+        }
+        _ => None,
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn packet_receiver(
-    video_decoder: ffmpeg::codec::decoder::Video,
+    video: Option<VideoInput>,
     audio_decoder: ffmpeg::codec::decoder::Audio,
-    video_decoded_tx: std::sync::mpsc::SyncSender<Video>,
-    audio_decoder_tx: std::sync::mpsc::SyncSender<Audio>,
+    video_decoded_tx: FrameSender,
+    audio_decoder_tx: FrameSender<DecodedAudio>,
     mut ictx: ffmpeg::format::context::Input,
-    video_stream_index: usize,
     audio_stream_index: usize,
-    break_flag: std::sync::Arc<std::sync::Mutex<bool>>,
-) -> std::thread::JoinHandle<Result<()>> {
-    std::thread::spawn(move || -> Result<()> {
-        let (audio_tx, audio_rx) = std::sync::mpsc::sync_channel(8);
-        let (video_tx, video_rx) = std::sync::mpsc::sync_channel(8);
+    audio_time_base: ffmpeg::Rational,
+    video_filter: Option<String>,
+    scaling: Scaling,
+    bounds: (u32, u32),
+    deinterlace: Switch,
+    audio_filter: Option<String>,
+    audio_spec: AudioSpec,
+    looping: bool,
+    policy: ErrorPolicy,
+    command_rx: std::sync::mpsc::Receiver<Command>,
+    events: std::sync::mpsc::Sender<WorkerEvent>,
+    cancel: CancellationToken,
+) -> WorkerThread {
+    spawn_worker(Worker::Demux, events.clone(), move || -> Result<()> {
+        let (audio_tx, audio_rx) = packet_queue(audio_time_base, &cancel);
 
-        let audio_thread_handle = audio_thread(audio_decoder, audio_rx, audio_decoder_tx);
-        let video_thread_handle = video_thread(video_decoder, video_rx, video_decoded_tx);
+        let audio_thread_handle = audio_thread(
+            audio_decoder,
+            audio_rx,
+            audio_decoder_tx,
+            audio_time_base,
+            audio_filter,
+            audio_spec,
+            policy,
+            events.clone(),
+            cancel.clone(),
+        );
+        let video_stream_index = video.as_ref().map(|video| video.index);
+        let video_time_base = video.as_ref().map(|video| video.time_base);
+        let (video_tx, video_thread_handle) = match video {
+            Some(video) => {
+                let (video_tx, video_rx) = packet_queue(video.time_base, &cancel);
+                let handle = video_thread(
+                    video.decoder,
+                    video_rx,
+                    video_decoded_tx,
+                    video.time_base,
+                    video.rotation,
+                    video_filter,
+                    scaling,
+                    bounds,
+                    deinterlace,
+                    policy,
+                    events.clone(),
+                    cancel.clone(),
+                );
+                (Some(video_tx), Some(handle))
+            }
+            // без видео очередь кадров сразу закрывается, и рендер видит что видео кончилось
+            None => {
+                drop(video_decoded_tx);
+                (None, None)
+            }
+        };
 
         // читаем все пакеты из потока через av_read_frame()
-        for (stream, packet) in ictx.packets() {
-            let packet = std::sync::Arc::new(packet);
-            // если пакет относится к видео
-            if stream.index() == video_stream_index {
-                video_tx.send(packet).unwrap_or(());
-            } else if stream.index() == audio_stream_index {
-                audio_tx.send(packet).unwrap_or(());
-            }
+        let mut read_packets = || -> Result<()> {
+            while !cancel.is_cancelled() {
+                while let Ok(command) = command_rx.try_recv() {
+                    match command {
+                        Command::Seek { seconds, serial } => {
+                            // seek без индекса потока ждёт время в AV_TIME_BASE
+                            let position = (seconds / f64::from(rescale::TIME_BASE)).round() as i64;
+                            // не получилось перемотать, значит играем дальше с того же места,
+                            // а очереди всё равно сбрасываем: рендер уже ждёт кадры новой серии
+                            if let Err(error) = ictx.seek(position, ..position) {
+                                events
+                                    .send(WorkerEvent::SeekFailed { seconds, error })
+                                    .unwrap_or(());
+                            }
+                            if let (Some(video_tx), Some(time_base)) = (&video_tx, video_time_base)
+                            {
+                                let pts = (seconds / f64::from(time_base)).round() as i64;
+                                if !video_tx.flush(Some(pts), serial) {
+                                    return Ok(());
+                                }
+                            }
+                            if !audio_tx.flush(None, serial) {
+                                return Ok(());
+                            }
+                        }
+                    }
+                }
 
-            if *break_flag.lock().unwrap() {
-                break;
+                // пакетов хватает обоим декодерам или памяти уже занято много, ждём пока разберут
+                // команды при этом продолжаем обрабатывать, поэтому ждём тут, а не в очереди
+                if queues_full(video_tx.as_ref(), &audio_tx) {
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                    continue;
+                }
+
+                // итератор каждый раз создаём заново, иначе он держит ictx и seek сделать нельзя
+                let (index, packet) = match ictx.packets().next() {
+                    Some((stream, packet)) => (stream.index(), packet),
+                    // с --loop в конце файла возвращаемся в начало,
+                    // а декодеры опустошаем и сбрасываем
+                    // начало файла это его start_time, у MPEG-TS он далеко не ноль
+                    None if looping => {
+                        let start = start_time(&ictx);
+                        if let Err(error) = ictx.seek(start, ..) {
+                            let seconds = start as f64 * f64::from(rescale::TIME_BASE);
+                            events
+                                .send(WorkerEvent::SeekFailed { seconds, error })
+                                .unwrap_or(());
+                            // повторить не получится, дальше как без --loop
+                            events.send(WorkerEvent::Eof(Worker::Demux)).unwrap_or(());
+                            return Ok(());
+                        }
+                        let video_open = video_tx.as_ref().map_or(true, PacketQueue::put_eof);
+                        if !video_open || !audio_tx.put_eof() {
+                            return Ok(());
+                        }
+                        continue;
+                    }
+                    None => {
+                        events.send(WorkerEvent::Eof(Worker::Demux)).unwrap_or(());
+                        return Ok(());
+                    }
+                };
+                // если пакет относится к видео
+                let sent = if Some(index) == video_stream_index {
+                    video_tx
+                        .as_ref()
+                        .map_or(true, |video_tx| video_tx.put(packet))
+                } else if index == audio_stream_index {
+                    audio_tx.put(packet)
+                } else {
+                    true
+                };
+                if !sent {
+                    return Ok(());
+                }
             }
+            Ok(())
+        };
+
+        let result = read_packets();
+        // если чтение сломалось, декодерам тоже больше нечего делать
+        if result.is_err() {
+            cancel.cancel();
         }
 
         drop(audio_tx);
         drop(video_tx);
 
-        audio_thread_handle.join().unwrap()?;
-        video_thread_handle.join().unwrap()?;
+        // свои ошибки декодеры присылают сами, здесь остаётся только паника
+        let mut handles = vec![(Worker::Audio, audio_thread_handle)];
+        if let Some(handle) = video_thread_handle {
+            handles.push((Worker::Video, handle));
+        }
+        for (worker, handle) in handles {
+            if handle.join().is_err() {
+                let error = anyhow!("thread panicked");
+                events
+                    .send(WorkerEvent::Failed { worker, error })
+                    .unwrap_or(());
+            }
+        }
 
-        Ok(())
+        result
     })
 }
 
+// как в ffplay: читать дальше не нужно, если очереди вместе заняли слишком много памяти
+// или если каждой из них хватает пакетов; иначе читаем, даже если одна из очередей уже длинная
+fn queues_full(video: Option<&PacketQueue>, audio: &PacketQueue) -> bool {
+    let video_bytes = video.map_or(0, PacketQueue::bytes);
+    let video_enough = video.map_or(true, PacketQueue::has_enough);
+    video_bytes + audio.bytes() > PACKET_QUEUE_BYTES || (video_enough && audio.has_enough())
+}
+
 fn video_thread(
     mut decoder: ffmpeg::codec::decoder::Video,
-    video_rx: std::sync::mpsc::Receiver<std::sync::Arc<ffmpeg::codec::packet::Packet>>,
-    result_tx: std::sync::mpsc::SyncSender<Video>,
-) -> std::thread::JoinHandle<Result<()>> {
-    std::thread::spawn(move || -> Result<()> {
+    video_rx: PacketReceiver,
+    result_tx: FrameSender,
+    time_base: ffmpeg::Rational,
+    rotation: Rotation,
+    filter_spec: Option<String>,
+    scaling: Scaling,
+    bounds: (u32, u32),
+    deinterlace: Switch,
+    policy: ErrorPolicy,
+    events: std::sync::mpsc::Sender<WorkerEvent>,
+    cancel: CancellationToken,
+) -> WorkerThread {
+    spawn_worker(Worker::Video, events.clone(), move || -> Result<()> {
         // определяем из какого формата в какой переводим
-        let mut context = Context::get(
-            decoder.format(),
-            decoder.width(),
-            decoder.height(),
-            Pixel::YUV420P,
-            decoder.width(),
-            decoder.height(),
-            Flags::BILINEAR,
-        )?;
+        // swscale работает только без графа, то есть без поворота, поэтому размер
+        // считается прямо от размера декодера
+        let mut context = scaling
+            .fit(decoder.width(), decoder.height(), bounds)
+            .context(
+                decoder.format(),
+                decoder.width(),
+                decoder.height(),
+                Pixel::YUV420P,
+            )?;
 
-        // функция для докодирования фреймов и записи их в файл
-        let mut receive_and_process_decoded_frames =
-            |decoder: &mut ffmpeg::decoder::Video| -> Result<(), ffmpeg::Error> {
-                // здесь происходит аллокация пустого фрейма через av_frame_alloc()
-                let mut decoded = Video::empty();
-                // пытаемся получить готовый фрейм из декодера через avcodec_receive_frame()
-                while decoder.receive_frame(&mut decoded).is_ok() {
-                    // здесь происходит аллокация пустого фрейма куда мы поместим модифицированный фрейм
-                    let mut frame_to_display = Video::empty();
-                    // переводим фрейм в нужный формат sws_scale()
-                    context.run(&decoded, &mut frame_to_display)?;
-                    //let frame_to_display = std::sync::Arc::new(frame_to_display);
-                    result_tx.send(frame_to_display).unwrap_or(());
+        // если видео надо повернуть или указан --vf, то кадры идут через граф фильтров,
+        // который сам приводит их к YUV420P и в конце которого стоит scale до размера окна
+        // тем же алгоритмом; поворот стоит первым, чтобы --vf видел кадры как их показывают
+        let filters = rotation
+            .filter()
+            .into_iter()
+            .chain(filter_spec)
+            .collect::<Vec<_>>();
+        let spec = if filters.is_empty() {
+            None
+        } else {
+            // размер на выходе --vf заранее не известен (crop, pad, поворот), поэтому
+            // размер считает последний scale: --scale, если задан, а потом уменьшаем
+            // с сохранением пропорций, если картинка не влезает на экран, как делает fit()
+            // на выходе BT.601 с ограниченным диапазоном, как и у Scaler, SDL настроен на него
+            let filters = filters
+                .into_iter()
+                .chain(scaling.filter())
+                .collect::<Vec<_>>();
+            Some(format!(
+                "{},scale=w='min(iw,{})':h='min(ih,{})':force_original_aspect_ratio=decrease:\
+                 force_divisible_by=2:flags={}:out_color_matrix=bt601:out_range=tv",
+                filters.join(","),
+                bounds.0,
+                bounds.1,
+                scaling.name()
+            ))
+        };
+        // граф собирается заново после конца файла и после перемотки:
+        // опустошённый граф новых кадров уже не берёт, а придержанные фильтрами кадры
+        // после перемотки не нужны
+        let build_graph =
+            |decoder: &ffmpeg::decoder::Video| -> Result<Option<ffmpeg::filter::Graph>> {
+                match &spec {
+                    Some(spec) => Ok(Some(filter::video_filter(
+                        spec,
+                        decoder,
+                        time_base,
+                        Pixel::YUV420P,
+                    )?)),
+                    None => Ok(None),
                 }
-                Ok(())
             };
+        let mut graph = build_graph(&decoder)?;
+
+        // отдаёт рендеру всё, что накопилось на выходе графа
+        // source это кадр, который только что ушёл в граф, из него делается скриншот
+        // false если рендер больше кадров не ждёт
+        let send_filtered = |graph: &mut ffmpeg::filter::Graph,
+                             source: Option<&Video>,
+                             serial: usize|
+         -> Result<bool, ffmpeg::Error> {
+            let time_base = filter::output_time_base(graph);
+            let mut filtered = Video::empty();
+            while graph
+                .get("out")
+                .unwrap()
+                .sink()
+                .frame(&mut filtered)
+                .is_ok()
+            {
+                let mut frame = DecodedFrame::filtered(filtered, serial, time_base);
+                if let Some(source) = source {
+                    frame = frame.with_source(frame_ref(source)?);
+                }
+                if !result_tx.send(frame) {
+                    return Ok(false);
+                }
+                filtered = Video::empty();
+            }
+            Ok(true)
+        };
+
+        // переводит кадр в то, что умеет рисовать draw_frame, и отдаёт рендеру
+        // false если рендер больше кадров не ждёт
+        let mut show = |graph: &mut Option<ffmpeg::filter::Graph>,
+                        decoded: &Video,
+                        serial: usize|
+         -> Result<bool, ffmpeg::Error> {
+            if let Some(graph) = graph.as_mut() {
+                // отдаём кадр в начало графа и забираем всё что получилось на выходе
+                graph.get("in").unwrap().source().add(decoded)?;
+                return send_filtered(graph, Some(decoded), serial);
+            }
+
+            // здесь происходит аллокация пустого фрейма куда мы поместим модифицированный фрейм
+            let mut frame_to_display = Video::empty();
+            // переводим фрейм в нужный формат sws_scale()
+            context.run(decoded, &mut frame_to_display)?;
+            let frame = DecodedFrame::new(frame_to_display, decoded, serial, time_base)
+                .with_source(frame_ref(decoded)?);
+            Ok(result_tx.send(frame))
+        };
+
+        // в конце файла забираем кадры, которые фильтры графа держат у себя
+        let finish_graph = |graph: &mut Option<ffmpeg::filter::Graph>,
+                            serial: usize|
+         -> Result<(), ffmpeg::Error> {
+            if let Some(graph) = graph.as_mut() {
+                graph.get("in").unwrap().source().flush()?;
+                send_filtered(graph, None, serial)?;
+            }
+            Ok(())
+        };
+
+        // функция для докодирования фреймов и записи их в файл
+        // деинтерлейсер и граф передаются отдельно, потому что между пакетами их пересобирают
+        // last после опустошения декодера: тогда забираем и кадры, придержанные фильтрами
+        let mut receive_and_process_decoded_frames = |decoder: &mut ffmpeg::decoder::Video,
+                                                      deinterlacer: &mut Deinterlacer,
+                                                      graph: &mut Option<ffmpeg::filter::Graph>,
+                                                      serial: usize,
+                                                      skip_until: Option<i64>,
+                                                      last: bool|
+         -> Result<(), ffmpeg::Error> {
+            // здесь происходит аллокация пустого фрейма через av_frame_alloc()
+            let mut decoded = Video::empty();
+            // пытаемся получить готовый фрейм из декодера через avcodec_receive_frame()
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                // после перемотки декодируем с ключевого кадра, но показывать нужно только с pts
+                if let (Some(target), Some(pts)) = (skip_until, decoded.timestamp()) {
+                    if pts < target {
+                        continue;
+                    }
+                }
+
+                if !deinterlacer.filter(&decoded, |frame| show(graph, frame, serial))? {
+                    return Ok(());
+                }
+            }
+            if last && deinterlacer.flush(|frame| show(graph, frame, serial))? {
+                finish_graph(graph, serial)?;
+            }
+            Ok(())
+        };
 
-        while let Ok(packet) = video_rx.recv() {
-            // посылаем пакет в декодер avcodec_send_packet()
-            decoder.send_packet(&*packet)?;
-            receive_and_process_decoded_frames(&mut decoder)?;
+        let mut deinterlacer = Deinterlacer::new(deinterlace);
+        let mut errors = DecodeErrors::new(policy);
+        let mut serial = 0;
+        let mut skip_until = None;
+        while let Some(message) = video_rx.recv() {
+            match message {
+                PacketMessage::Packet(packet) => {
+                    // D переключает деинтерлейсинг из главного потока
+                    deinterlacer.update(&decoder, time_base)?;
+                    // посылаем пакет в декодер avcodec_send_packet()
+                    let skipped = errors
+                        .send_packet(&mut decoder, &packet)
+                        .context("corrupt video packet")?;
+                    if let Some(error) = skipped {
+                        let event = WorkerEvent::CorruptPacket {
+                            worker: Worker::Video,
+                            error,
+                            skipped: errors.skipped(),
+                        };
+                        events.send(event).unwrap_or(());
+                    }
+                    receive_and_process_decoded_frames(
+                        &mut decoder,
+                        &mut deinterlacer,
+                        &mut graph,
+                        serial,
+                        skip_until,
+                        false,
+                    )?;
+                }
+                PacketMessage::Flush {
+                    pts,
+                    serial: new_serial,
+                } => {
+                    // avcodec_flush_buffers() выкидывает всё что декодер успел накопить
+                    decoder.flush();
+                    deinterlacer.reset(&decoder, time_base)?;
+                    graph = build_graph(&decoder)?;
+                    serial = new_serial;
+                    skip_until = pts;
+                }
+                PacketMessage::Eof => {
+                    // send_eof() переводит декодер в режим опустошения, забираем последние кадры
+                    drain(&mut decoder, |decoder| {
+                        receive_and_process_decoded_frames(
+                            decoder,
+                            &mut deinterlacer,
+                            &mut graph,
+                            serial,
+                            skip_until,
+                            true,
+                        )
+                    })?;
+                    decoder.flush();
+                    deinterlacer.reset(&decoder, time_base)?;
+                    graph = build_graph(&decoder)?;
+                    skip_until = None;
+                }
+            }
+        }
+        if !cancel.is_cancelled() {
+            // пакеты кончились, забираем кадры, которые декодер и фильтры ещё держат у себя
+            drain(&mut decoder, |decoder| {
+                receive_and_process_decoded_frames(
+                    decoder,
+                    &mut deinterlacer,
+                    &mut graph,
+                    serial,
+                    skip_until,
+                    true,
+                )
+            })?;
+            events.send(WorkerEvent::Eof(Worker::Video)).unwrap_or(());
         }
-        decoder.send_eof()?;
 
         Ok(())
     })
@@ -258,69 +1258,267 @@ fn video_thread(
 
 fn audio_thread(
     mut decoder: ffmpeg::codec::decoder::Audio,
-    audio_rx: std::sync::mpsc::Receiver<std::sync::Arc<ffmpeg::codec::packet::Packet>>,
-    result_tx: std::sync::mpsc::SyncSender<Audio>,
-) -> std::thread::JoinHandle<Result<()>> {
-    std::thread::spawn(move || -> Result<()> {
+    audio_rx: PacketReceiver,
+    result_tx: FrameSender<DecodedAudio>,
+    time_base: ffmpeg::Rational,
+    filter_spec: Option<String>,
+    spec: AudioSpec,
+    policy: ErrorPolicy,
+    events: std::sync::mpsc::Sender<WorkerEvent>,
+    cancel: CancellationToken,
+) -> WorkerThread {
+    spawn_worker(Worker::Audio, events.clone(), move || -> Result<()> {
+        // частота и число каналов берутся у аудио устройства, а не у декодера
+        let channel_layout = ffmpeg::ChannelLayout::default(i32::from(spec.channels));
+        let rate = spec.freq as u32;
+
         let mut a_context = AudioContext::get(
             decoder.format(),
             decoder.channel_layout(),
             decoder.rate(),
             Sample::I16(AudioType::Packed),
-            decoder.channel_layout(),
-            decoder.rate(),
+            channel_layout,
+            rate,
         )?;
 
-        let mut receive_and_process_decoded_frames =
-            |decoder: &mut ffmpeg::decoder::Audio| -> Result<(), ffmpeg::Error> {
-                let mut decoded = Audio::empty();
-                while decoder.receive_frame(&mut decoded).is_ok() {
-                    let mut frame_to_play = Audio::empty();
-                    a_context.run(&decoded, &mut frame_to_play)?;
-                    //let frame_to_play = std::sync::Arc::new(frame_to_play);
-                    result_tx.send(frame_to_play).unwrap_or(())
+        // выход графа совпадает с тем, что ждёт аудио устройство, так что ресемплер тут не нужен
+        // как и у видео, граф собирается заново после конца файла и после перемотки
+        let build_graph =
+            |decoder: &ffmpeg::decoder::Audio| -> Result<Option<ffmpeg::filter::Graph>> {
+                match &filter_spec {
+                    Some(spec) => Ok(Some(filter::audio_filter(
+                        spec,
+                        decoder,
+                        time_base,
+                        Sample::I16(AudioType::Packed),
+                        channel_layout,
+                        rate,
+                    )?)),
+                    None => Ok(None),
                 }
-                Ok(())
             };
+        let mut graph = build_graph(&decoder)?;
+
+        // отдаёт всё, что накопилось на выходе графа, false если кадры больше не ждут
+        // время берём у самих кадров: фильтры вроде atempo его меняют
+        let send_filtered = |graph: &mut ffmpeg::filter::Graph, serial: usize| -> bool {
+            let time_base = filter::output_time_base(graph);
+            let mut filtered = Audio::empty();
+            while graph
+                .get("out")
+                .unwrap()
+                .sink()
+                .frame(&mut filtered)
+                .is_ok()
+            {
+                let pts = filtered
+                    .timestamp()
+                    .map(|pts| pts as f64 * f64::from(time_base));
+                let frame = DecodedAudio {
+                    frame: filtered,
+                    serial,
+                    pts,
+                };
+                if !result_tx.send(frame) {
+                    return false;
+                }
+                filtered = Audio::empty();
+            }
+            true
+        };
+
+        // в конце файла забираем звук, который фильтры графа держат у себя
+        let finish_graph = |graph: &mut Option<ffmpeg::filter::Graph>,
+                            serial: usize|
+         -> Result<(), ffmpeg::Error> {
+            if let Some(graph) = graph.as_mut() {
+                graph.get("in").unwrap().source().flush()?;
+                send_filtered(graph, serial);
+            }
+            Ok(())
+        };
 
-        while let Ok(packet) = audio_rx.recv() {
-            decoder.send_packet(&*packet)?;
-            receive_and_process_decoded_frames(&mut decoder)?;
+        let mut receive_and_process_decoded_frames = |decoder: &mut ffmpeg::decoder::Audio,
+                                                      graph: &mut Option<ffmpeg::filter::Graph>,
+                                                      serial: usize|
+         -> Result<(), ffmpeg::Error> {
+            let mut decoded = Audio::empty();
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                if let Some(graph) = graph.as_mut() {
+                    graph.get("in").unwrap().source().add(&decoded)?;
+                    if !send_filtered(graph, serial) {
+                        return Ok(());
+                    }
+                    continue;
+                }
+
+                let pts = decoded
+                    .timestamp()
+                    .map(|pts| pts as f64 * f64::from(time_base));
+                let mut frame_to_play = Audio::empty();
+                a_context.run(&decoded, &mut frame_to_play)?;
+                let frame = DecodedAudio {
+                    frame: frame_to_play,
+                    serial,
+                    pts,
+                };
+                if !result_tx.send(frame) {
+                    return Ok(());
+                }
+            }
+            Ok(())
+        };
+
+        let mut errors = DecodeErrors::new(policy);
+        let mut serial = 0;
+        while let Some(message) = audio_rx.recv() {
+            match message {
+                PacketMessage::Packet(packet) => {
+                    let skipped = errors
+                        .send_packet(&mut decoder, &packet)
+                        .context("corrupt audio packet")?;
+                    if let Some(error) = skipped {
+                        let event = WorkerEvent::CorruptPacket {
+                            worker: Worker::Audio,
+                            error,
+                            skipped: errors.skipped(),
+                        };
+                        events.send(event).unwrap_or(());
+                    }
+                    receive_and_process_decoded_frames(&mut decoder, &mut graph, serial)?;
+                }
+                PacketMessage::Flush {
+                    serial: new_serial, ..
+                } => {
+                    decoder.flush();
+                    graph = build_graph(&decoder)?;
+                    serial = new_serial;
+                }
+                PacketMessage::Eof => {
+                    drain(&mut decoder, |decoder| {
+                        receive_and_process_decoded_frames(decoder, &mut graph, serial)
+                    })?;
+                    finish_graph(&mut graph, serial)?;
+                    decoder.flush();
+                    graph = build_graph(&decoder)?;
+                }
+            }
+        }
+        if !cancel.is_cancelled() {
+            drain(&mut decoder, |decoder| {
+                receive_and_process_decoded_frames(decoder, &mut graph, serial)
+            })?;
+            finish_graph(&mut graph, serial)?;
+            events.send(WorkerEvent::Eof(Worker::Audio)).unwrap_or(());
         }
-        decoder.send_eof()?;
 
         Ok(())
     })
 }
 
+// ждём следующий кадр текущей серии, кадры старых серий выбрасываем
+// пока ждём видео, аудио тоже надо вычитывать, иначе поток чтения встанет на полной очереди
+fn next_frame(
+    video_rx: &FrameQueue,
+    audio_rx: &FrameQueue<DecodedAudio>,
+    audio_device: &AudioSink,
+    serial: usize,
+) -> Option<DecodedFrame> {
+    loop {
+        match video_rx.recv_timeout(std::time::Duration::from_millis(10)) {
+            Ok(frame) if frame.serial == serial => return Some(frame),
+            Ok(_) | Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return None,
+        }
+
+        // на паузе звуковая карта не играет, и то что в неё не влезает, выбрасываем
+        while let Ok(frame_to_play) = audio_rx.try_recv() {
+            if frame_to_play.serial == serial && !audio_queue_full(audio_device) {
+                audio_device.queue(frame_to_play.samples(audio_device.spec().channels));
+            }
+        }
+    }
+}
+
+// в очереди звуковой карты уже AUDIO_QUEUE_SECONDS звука
+fn audio_queue_full(audio_device: &AudioSink) -> bool {
+    let spec = audio_device.spec();
+    let bytes_per_second = 2.0 * f64::from(spec.channels) * f64::from(spec.freq);
+    f64::from(audio_device.size()) >= AUDIO_QUEUE_SECONDS * bytes_per_second
+}
+
+// позиция проигрывания в секундах: время показанного кадра,
+// а без видео время сэмплов, которые звуковая карта играет прямо сейчас
+// сразу после перемотки позиция неизвестна, пока не придёт кадр или звук новой серии
+fn playback_position(
+    current_frame: &Option<DecodedFrame>,
+    serial: usize,
+    audio_pts: Option<f64>,
+    audio_device: &AudioSink,
+) -> Option<f64> {
+    if let Some(frame) = current_frame {
+        return frame.pts.filter(|_| frame.serial == serial);
+    }
+    let spec = audio_device.spec();
+    let bytes_per_second = 2 * f64::from(spec.channels) * f64::from(spec.freq);
+    audio_pts.map(|pts| pts - f64::from(audio_device.size()) / bytes_per_second)
+}
+
+// размер окна от прошлого файла, если оно уже есть
+fn canvas_size(output: &Option<Output>) -> Option<(u32, u32)> {
+    output.as_ref().map(|output| output.canvas.window().size())
+}
+
 fn draw_frame(
-    frame: &mut Video,
+    decoded: &mut DecodedFrame,
     canvas: &mut WindowCanvas,
     texture_creator: &TextureCreator<WindowContext>,
+    progress: &Progress,
+    osd: &Osd,
 ) -> Result<()> {
+    let frame = &mut decoded.frame;
     let mut texture = texture_creator
         .create_texture_streaming(PixelFormatEnum::YV12, frame.width(), frame.height())
         .context("couldn't create texture")?;
+    // строки кадра выровнены и могут быть длиннее самой картинки,
+    // поэтому SDL копирует плоскости построчно с шагом строки кадра
+    // длину плоскостей цветности SDL сверяет с шаг * высота / 2, в том числе при нечётной высоте
+    let rows = frame.height() as usize;
     texture
-        .with_lock(None, |buffer: &mut [u8], _: usize| {
-            let mut index: usize = 0;
-            for (i, byte) in frame.data(0).iter().enumerate() {
-                buffer[i] = *byte;
-                index += 1;
-            }
-
-            for byte in frame.data(2) {
-                buffer[index] = *byte;
-                index += 1;
-            }
-
-            for byte in frame.data(1) {
-                buffer[index] = *byte;
-                index += 1;
-            }
-        })
+        .update_yuv(
+            None,
+            &frame.data(0)[..frame.stride(0) * rows],
+            frame.stride(0),
+            &frame.data(1)[..frame.stride(1) * rows / 2],
+            frame.stride(1),
+            &frame.data(2)[..frame.stride(2) * rows / 2],
+            frame.stride(2),
+        )
+        .context("couldn't update texture")?;
+    // кадр вписывается в окно с сохранением пропорций, остальное закрашивается чёрным:
+    // окно может быть в полноэкранном режиме, а фильтры --vf менять размер кадра на ходу
+    let (width, height) = canvas.output_size().map_err(|e| anyhow!(e))?;
+    let ratio = f64::min(
+        f64::from(width) / f64::from(frame.width().max(1)),
+        f64::from(height) / f64::from(frame.height().max(1)),
+    );
+    let (to_width, to_height) = (
+        (f64::from(frame.width()) * ratio) as u32,
+        (f64::from(frame.height()) * ratio) as u32,
+    );
+    let target = Rect::new(
+        (width.saturating_sub(to_width) / 2) as i32,
+        (height.saturating_sub(to_height) / 2) as i32,
+        to_width.max(1),
+        to_height.max(1),
+    );
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.clear();
+    canvas
+        .copy(&texture, None, Some(target))
         .map_err(|e| anyhow!(e))?;
-    canvas.copy(&texture, None, None).map_err(|e| anyhow!(e))?;
+    progress.draw(canvas, decoded.pts)?;
+    osd.draw(canvas)?;
     canvas.present();
     Ok(())
 }
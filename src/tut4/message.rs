@@ -0,0 +1,174 @@
+use ffmpeg::codec::packet::Packet;
+use ffmpeg::util::frame::audio::Audio;
+use ffmpeg::util::frame::video::Video;
+use ffmpeg::{picture, Rational};
+use std::fmt;
+use std::sync::Arc;
+
+// команды от главного потока потоку чтения пакетов
+pub enum Command {
+    // перейти к ближайшему ключевому кадру перед позицией в секундах
+    // и начать новую серию кадров
+    Seek { seconds: f64, serial: usize },
+}
+
+// то что поток чтения отдаёт потокам декодеров
+pub enum PacketMessage {
+    Packet(Arc<Packet>),
+    // после перемотки декодер надо сбросить, а кадры раньше pts выбросить
+    Flush { pts: Option<i64>, serial: usize },
+    // файл закончился и читается заново с начала: декодер надо опустошить и сбросить
+    Eof,
+}
+
+// декодированный кадр вместе с информацией о том откуда он взялся
+// время уже переведено в секунды, чтобы рендеру не нужен был time base потока
+pub struct DecodedFrame {
+    pub frame: Video,
+    // кадр до поворота, --vf и масштабирования под окно, из него делается скриншот
+    // None у кадров, которые граф фильтров отдал в конце файла, для них есть только frame
+    pub source: Option<Video>,
+    // номер серии, кадры со старым номером остались от прошлой позиции и не нужны
+    pub serial: usize,
+    pub pts: Option<f64>,
+    pub duration: f64,
+    pub keyframe: bool,
+    pub kind: picture::Type,
+    pub packet_size: usize,
+}
+
+impl DecodedFrame {
+    // frame это уже сконвертированный кадр, а информацию берём из кадра декодера
+    pub fn new(frame: Video, decoded: &Video, serial: usize, time_base: Rational) -> DecodedFrame {
+        let packet = decoded.packet();
+        let seconds = f64::from(time_base);
+        DecodedFrame {
+            frame,
+            source: None,
+            serial,
+            pts: decoded.timestamp().map(|pts| pts as f64 * seconds),
+            // у некоторых файлов длительность кадра неизвестна, тогда считаем её одним тиком
+            duration: packet.duration.max(1) as f64 * seconds,
+            keyframe: decoded.is_key(),
+            kind: decoded.kind(),
+            packet_size: packet.size,
+        }
+    }
+
+    // кадр с выхода графа фильтров: фильтры могут задержать кадры или поменять им время,
+    // поэтому информацию берём у него самого, time_base это time base выхода графа
+    pub fn filtered(frame: Video, serial: usize, time_base: Rational) -> DecodedFrame {
+        let mut filtered = DecodedFrame::new(Video::empty(), &frame, serial, time_base);
+        filtered.frame = frame;
+        filtered
+    }
+
+    pub fn with_source(mut self, source: Video) -> DecodedFrame {
+        self.source = Some(source);
+        self
+    }
+
+    // сколько памяти занимают данные кадров, по этому размеру ограничивается FrameQueue
+    pub fn size(&self) -> usize {
+        Some(&self.frame)
+            .into_iter()
+            .chain(&self.source)
+            .flat_map(|frame| (0..frame.planes()).map(move |plane| frame.data(plane).len()))
+            .sum()
+    }
+
+    // строка для OSD в режиме покадрового просмотра
+    pub fn describe(&self) -> String {
+        format!(
+            "pts {:.3}s | {:?} frame{} | packet {} bytes",
+            self.pts.unwrap_or(f64::NAN),
+            self.kind,
+            if self.keyframe { " (key)" } else { "" },
+            self.packet_size
+        )
+    }
+}
+
+// сэмплы уже в формате звуковой карты
+pub struct DecodedAudio {
+    pub frame: Audio,
+    pub serial: usize,
+    // время начала в секундах
+    pub pts: Option<f64>,
+}
+
+impl DecodedAudio {
+    // сэмплы всех каналов вперемешку, без выравнивания в конце буфера
+    pub fn samples(&self, channels: u8) -> &[i16] {
+        let samples = unsafe { self.frame.data(0).align_to::<i16>() }.1;
+        let len = self.frame.samples() * usize::from(channels);
+        &samples[..len.min(samples.len())]
+    }
+}
+
+// какой из потоков конвейера прислал событие
+#[derive(Clone, Copy, Debug)]
+pub enum Worker {
+    Demux,
+    Video,
+    Audio,
+}
+
+impl fmt::Display for Worker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Worker::Demux => "demux",
+            Worker::Video => "video",
+            Worker::Audio => "audio",
+        })
+    }
+}
+
+// события от потоков конвейера главному потоку, в том числе все их ошибки
+pub enum WorkerEvent {
+    // пакет не удалось декодировать, он пропущен
+    // skipped это сколько пакетов этот поток уже пропустил
+    CorruptPacket {
+        worker: Worker,
+        error: ffmpeg::Error,
+        skipped: usize,
+    },
+    // поток дошёл до конца файла
+    Eof(Worker),
+    // перемотать не получилось, чтение продолжается с того же места
+    // seconds это время, куда перематывали, как в Command::Seek
+    SeekFailed {
+        seconds: f64,
+        error: ffmpeg::Error,
+    },
+    // поток остановился из-за ошибки
+    Failed {
+        worker: Worker,
+        error: anyhow::Error,
+    },
+}
+
+impl fmt::Display for WorkerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WorkerEvent::CorruptPacket {
+                worker,
+                error,
+                skipped,
+            } => write!(
+                f,
+                "{}: corrupt packet skipped ({} so far): {}",
+                worker, skipped, error
+            ),
+            WorkerEvent::Eof(worker) => write!(f, "{}: end of file", worker),
+            WorkerEvent::SeekFailed { seconds, error } => write!(
+                f,
+                "{}: couldn't seek to {:.3}s: {}",
+                Worker::Demux,
+                seconds,
+                error
+            ),
+            WorkerEvent::Failed { worker, error } => write!(f, "{}: {:#}", worker, error),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use crate::deinterlace::Method;
+use anyhow::{anyhow, Result};
+use fftut::args::value;
+use fftut::decode::{DecoderBuilder, ErrorPolicy};
+use fftut::scale::Scaling;
+use std::env;
+
+// параметры командной строки плеера
+pub struct Options {
+    // файлы и плейлисты в порядке проигрывания
+    pub inputs: Vec<String>,
+    // описание графа фильтров для видео, например "scale=640:-2,hflip"
+    pub video_filter: Option<String>,
+    // описание графа фильтров для аудио, например "loudnorm"
+    pub audio_filter: Option<String>,
+    // начинать заново по достижении конца файла
+    pub looping: bool,
+    pub error_policy: ErrorPolicy,
+    // потоки декодирования из --threads и --threading
+    pub decoder: DecoderBuilder,
+    // размер и алгоритм перевода кадров из --scale и --scaler
+    pub scaling: Scaling,
+    // чем убирать гребёнку с чересстрочного видео, None если выключено до нажатия D
+    pub deinterlace: Option<Method>,
+    // без окна и звуковой карты, для CI и серверов; часы при этом идут как обычно
+    pub headless: bool,
+    // только декодировать и мерить скорость, ничего не показывая
+    pub bench: bool,
+}
+
+impl Options {
+    pub fn parse() -> Result<Options> {
+        let mut inputs = Vec::new();
+        let mut video_filter = None;
+        let mut audio_filter = None;
+        let mut looping = false;
+        let mut error_policy = ErrorPolicy::Skip;
+        let mut decoder = DecoderBuilder::new();
+        let mut scaling = Scaling::new();
+        let mut deinterlace = Some(Method::Yadif);
+        let mut headless = false;
+        let mut bench = false;
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--vf" => video_filter = Some(value(&mut args, &arg)?),
+                "--af" => audio_filter = Some(value(&mut args, &arg)?),
+                "--loop" => looping = true,
+                "--strict" => error_policy = ErrorPolicy::Abort,
+                "--scale" | "--scaler" => {
+                    scaling = scaling.option(&arg, &value(&mut args, &arg)?)?
+                }
+                "--deinterlace" => deinterlace = Method::parse(&value(&mut args, &arg)?)?,
+                "--threads" | "--threading" => {
+                    decoder = decoder.option(&arg, &value(&mut args, &arg)?)?
+                }
+                "--headless" => headless = true,
+                "--bench" => bench = true,
+                _ if arg.starts_with("--") => return Err(anyhow!("unknown option {}", arg)),
+                _ => inputs.push(arg),
+            }
+        }
+
+        if inputs.is_empty() {
+            return Err(anyhow!("no input specified"));
+        }
+
+        Ok(Options {
+            inputs,
+            video_filter,
+            audio_filter,
+            looping,
+            error_policy,
+            decoder,
+            scaling,
+            deinterlace,
+            headless,
+            bench,
+        })
+    }
+}
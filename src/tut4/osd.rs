@@ -0,0 +1,192 @@
+use anyhow::{anyhow, Result};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+use std::time::{Duration, Instant};
+
+// сколько висит сообщение, если его не закрепили
+const SHOW_TIME: Duration = Duration::from_secs(3);
+// отступ от края окна и поля вокруг текста в точках шрифта
+const MARGIN: u32 = 2;
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+
+// сообщения поверх видео: статус, глава, описание кадра на паузе
+// в заголовке окна их не видно в полноэкранном режиме, поэтому они рисуются на канвасе
+// шрифт свой, 5x7 точек, только ASCII и только заглавные, ради OSD тянуть SDL_ttf не хочется
+pub struct Osd {
+    text: Option<String>,
+    // до какого момента показывать, None у закреплённого сообщения
+    until: Option<Instant>,
+}
+
+impl Osd {
+    pub fn new() -> Osd {
+        Osd {
+            text: None,
+            until: None,
+        }
+    }
+
+    // показывает text несколько секунд
+    pub fn show<S: Into<String>>(&mut self, text: S) {
+        self.text = Some(text.into());
+        self.until = Some(Instant::now() + SHOW_TIME);
+    }
+
+    // показывает text пока его не сменят или не уберут
+    pub fn pin<S: Into<String>>(&mut self, text: S) {
+        self.text = Some(text.into());
+        self.until = None;
+    }
+
+    pub fn hide(&mut self) {
+        self.text = None;
+        self.until = None;
+    }
+
+    // убирает просроченное сообщение, true если оно только что пропало с экрана
+    // на паузе кадры сами не перерисовываются, тогда кадр нужно нарисовать заново
+    pub fn expire(&mut self) -> bool {
+        match self.until {
+            Some(until) if self.text.is_some() && Instant::now() >= until => {
+                self.hide();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // рисует поверх того что уже на канвасе, present() остаётся за вызывающим
+    pub fn draw(&self, canvas: &mut WindowCanvas) -> Result<()> {
+        let text = match &self.text {
+            Some(text) if self.until.map_or(true, |until| Instant::now() < until) => text,
+            _ => return Ok(()),
+        };
+        let (width, height) = canvas.output_size().map_err(|e| anyhow!(e))?;
+        // точка шрифта растёт вместе с окном, чтобы текст читался и на большом экране
+        let dot = (height / 240).max(1).min(4);
+        let advance = (GLYPH_WIDTH + 1) * dot;
+        // что не влезает по ширине, просто обрезается
+        let fits = (width.saturating_sub(4 * MARGIN * dot) / advance) as usize;
+        let chars = text.chars().take(fits).collect::<Vec<_>>();
+        if chars.is_empty() {
+            return Ok(());
+        }
+
+        let origin = (MARGIN * dot) as i32;
+        let padding = MARGIN * dot;
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas
+            .fill_rect(Rect::new(
+                origin,
+                origin,
+                chars.len() as u32 * advance - dot + 2 * padding,
+                GLYPH_HEIGHT * dot + 2 * padding,
+            ))
+            .map_err(|e| anyhow!(e))?;
+
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        let left = origin + padding as i32;
+        let top = origin + padding as i32;
+        for (index, c) in chars.into_iter().enumerate() {
+            let x = left + (index as u32 * advance) as i32;
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                        continue;
+                    }
+                    canvas
+                        .fill_rect(Rect::new(
+                            x + (column * dot) as i32,
+                            top + (row as u32 * dot) as i32,
+                            dot,
+                            dot,
+                        ))
+                        .map_err(|e| anyhow!(e))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// строки символа сверху вниз, старший из пяти бит это левая точка
+// строчные рисуются заглавными, а всё чего нет в шрифте знаком вопроса
+fn glyph(c: char) -> [u8; 7] {
+    let c = c.to_ascii_uppercase();
+    GLYPHS
+        .iter()
+        .find(|(glyph, _)| *glyph == c)
+        .or_else(|| GLYPHS.iter().find(|(glyph, _)| *glyph == '?'))
+        .map(|(_, rows)| *rows)
+        .unwrap()
+}
+
+#[rustfmt::skip]
+const GLYPHS: &[(char, [u8; 7])] = &[
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('!', [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100]),
+    ('"', [0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('#', [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010]),
+    ('$', [0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100]),
+    ('%', [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011]),
+    ('&', [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101]),
+    ('\'', [0b00100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+    ('*', [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    ('/', [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    (';', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000]),
+    ('<', [0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010]),
+    ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
+    ('>', [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000]),
+    ('?', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+    ('@', [0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('[', [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110]),
+    ('\\', [0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000]),
+    (']', [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110]),
+    ('_', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111]),
+    ('|', [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+];
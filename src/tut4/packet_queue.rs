@@ -0,0 +1,306 @@
+use crate::cancel::CancellationToken;
+use crate::message::PacketMessage;
+use ffmpeg::codec::packet::Packet;
+use ffmpeg::Rational;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+
+// очереди достаточно, если в ней больше стольких пакетов и больше стольких секунд,
+// те же пороги что у ffplay
+const MIN_PACKETS: usize = 25;
+const MIN_DURATION: f64 = 1.0;
+
+// очередь пакетов одного потока между потоком чтения и декодером
+// в отличие от sync_channel она никогда не блокирует поток чтения:
+// сколько читать решает сам поток чтения по bytes() и has_enough() всех очередей сразу,
+// поэтому при неравномерном перемежении он не встаёт на одной очереди, пока другая пустая
+// при отмене конвейера ждущий в recv() декодер просыпается сразу
+pub fn packet_queue(
+    time_base: Rational,
+    cancel: &CancellationToken,
+) -> (PacketQueue, PacketReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            messages: VecDeque::new(),
+            packets: 0,
+            bytes: 0,
+            duration: 0,
+            finished: false,
+            closed: false,
+        }),
+        changed: Condvar::new(),
+        time_base,
+        cancel: cancel.clone(),
+    });
+    // слабая ссылка, иначе очередь и токен держат друг друга и не удаляются никогда
+    let waker: Weak<Shared> = Arc::downgrade(&shared);
+    cancel.on_cancel(move || {
+        if let Some(shared) = waker.upgrade() {
+            let _state = shared.state.lock().unwrap();
+            shared.changed.notify_all();
+        }
+    });
+    (
+        PacketQueue {
+            shared: shared.clone(),
+        },
+        PacketReceiver { shared },
+    )
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+    time_base: Rational,
+    cancel: CancellationToken,
+}
+
+struct State {
+    messages: VecDeque<PacketMessage>,
+    packets: usize,
+    bytes: usize,
+    // суммарная длительность пакетов в time base потока
+    duration: i64,
+    // поток чтения больше ничего не пришлёт
+    finished: bool,
+    // декодер больше ничего не прочитает
+    closed: bool,
+}
+
+// сторона потока чтения, при удалении очередь считается законченной
+pub struct PacketQueue {
+    shared: Arc<Shared>,
+}
+
+impl PacketQueue {
+    // false если декодер уже закрыл очередь
+    pub fn put(&self, packet: Packet) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.closed {
+            return false;
+        }
+        state.packets += 1;
+        state.bytes += packet.size();
+        state.duration += packet.duration();
+        state
+            .messages
+            .push_back(PacketMessage::Packet(Arc::new(packet)));
+        self.shared.changed.notify_all();
+        true
+    }
+
+    pub fn put_eof(&self) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.closed {
+            return false;
+        }
+        state.messages.push_back(PacketMessage::Eof);
+        self.shared.changed.notify_all();
+        true
+    }
+
+    // после перемотки пакеты старой серии декодировать уже незачем, выкидываем их сразу
+    // а декодер получит Flush и начнёт новую серию
+    pub fn flush(&self, pts: Option<i64>, serial: usize) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.closed {
+            return false;
+        }
+        state.messages.clear();
+        state.packets = 0;
+        state.bytes = 0;
+        state.duration = 0;
+        state
+            .messages
+            .push_back(PacketMessage::Flush { pts, serial });
+        self.shared.changed.notify_all();
+        true
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.shared.state.lock().unwrap().bytes
+    }
+
+    // пакетов хватает декодеру на какое-то время вперёд, читать для этого потока пока не нужно
+    // закрытой очереди пакеты не нужны вовсе
+    // у многих файлов длительность пакетов не указана, тогда хватает одного числа пакетов,
+    // как в ffplay, иначе такая очередь никогда не наполнится и файл прочитается в память
+    pub fn has_enough(&self) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        let duration = state.duration as f64 * f64::from(self.shared.time_base);
+        state.closed
+            || (state.packets > MIN_PACKETS && (state.duration == 0 || duration > MIN_DURATION))
+    }
+}
+
+impl Drop for PacketQueue {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().finished = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+// сторона декодера, при удалении очередь закрывается и поток чтения перестаёт в неё писать
+pub struct PacketReceiver {
+    shared: Arc<Shared>,
+}
+
+impl PacketReceiver {
+    // ждёт следующее сообщение, None если поток чтения закончил или конвейер останавливается
+    pub fn recv(&self) -> Option<PacketMessage> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if self.shared.cancel.is_cancelled() {
+                return None;
+            }
+            if let Some(message) = state.messages.pop_front() {
+                if let PacketMessage::Packet(packet) = &message {
+                    state.packets -= 1;
+                    state.bytes -= packet.size();
+                    state.duration -= packet.duration();
+                }
+                return Some(message);
+            }
+            if state.finished {
+                return None;
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        }
+    }
+}
+
+impl Drop for PacketReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.closed = true;
+        state.messages.clear();
+        state.packets = 0;
+        state.bytes = 0;
+        state.duration = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::thread;
+    use std::time::Duration;
+
+    const WAIT: Duration = Duration::from_secs(5);
+    // за это время заблокированный поток точно успел бы пройти дальше
+    const BLOCKED: Duration = Duration::from_millis(50);
+
+    fn packet(size: usize, duration: i64) -> Packet {
+        let mut packet = Packet::copy(&vec![0; size]);
+        packet.set_duration(duration);
+        packet
+    }
+
+    // читает одно сообщение в другом потоке
+    fn recv_later(receiver: PacketReceiver) -> Receiver<Option<PacketMessage>> {
+        let (done_tx, done_rx) = channel();
+        thread::spawn(move || {
+            let message = receiver.recv();
+            done_tx.send(message).unwrap();
+        });
+        done_rx
+    }
+
+    #[test]
+    fn blocked_recv_wakes_on_put() {
+        let cancel = CancellationToken::new();
+        let (queue, receiver) = packet_queue(Rational::new(1, 1000), &cancel);
+        let done = recv_later(receiver);
+        assert!(done.recv_timeout(BLOCKED).is_err());
+
+        assert!(queue.put(packet(10, 0)));
+        match done.recv_timeout(WAIT).unwrap() {
+            Some(PacketMessage::Packet(packet)) => assert_eq!(packet.size(), 10),
+            _ => panic!("expected a packet"),
+        }
+    }
+
+    #[test]
+    fn flush_drops_stale_packets() {
+        let cancel = CancellationToken::new();
+        let (queue, receiver) = packet_queue(Rational::new(1, 1000), &cancel);
+        assert!(queue.put(packet(10, 40)));
+        assert!(queue.put(packet(20, 40)));
+        assert_eq!(queue.bytes(), 30);
+
+        assert!(queue.flush(Some(5000), 2));
+        assert_eq!(queue.bytes(), 0);
+        assert!(queue.put(packet(30, 40)));
+
+        // пакеты старой серии выброшены, декодер сначала получает Flush с новой серией
+        match receiver.recv() {
+            Some(PacketMessage::Flush { pts, serial }) => {
+                assert_eq!(pts, Some(5000));
+                assert_eq!(serial, 2);
+            }
+            _ => panic!("expected a flush"),
+        }
+        match receiver.recv() {
+            Some(PacketMessage::Packet(packet)) => assert_eq!(packet.size(), 30),
+            _ => panic!("expected a packet"),
+        }
+        assert_eq!(queue.bytes(), 0);
+    }
+
+    #[test]
+    fn cancel_wakes_blocked_recv() {
+        let cancel = CancellationToken::new();
+        let (_queue, receiver) = packet_queue(Rational::new(1, 1000), &cancel);
+        let done = recv_later(receiver);
+        assert!(done.recv_timeout(BLOCKED).is_err());
+
+        cancel.cancel();
+        assert!(done.recv_timeout(WAIT).unwrap().is_none());
+    }
+
+    #[test]
+    fn eof_then_finished() {
+        let cancel = CancellationToken::new();
+        let (queue, receiver) = packet_queue(Rational::new(1, 1000), &cancel);
+        assert!(queue.put_eof());
+        drop(queue);
+        assert!(matches!(receiver.recv(), Some(PacketMessage::Eof)));
+        assert!(receiver.recv().is_none());
+    }
+
+    #[test]
+    fn has_enough_counts_packets_and_duration() {
+        let cancel = CancellationToken::new();
+        let (queue, receiver) = packet_queue(Rational::new(1, 1000), &cancel);
+        // 26 пакетов по 20 мс это полсекунды, мало
+        for _ in 0..=MIN_PACKETS {
+            assert!(queue.put(packet(100, 20)));
+        }
+        assert_eq!(queue.bytes(), 100 * (MIN_PACKETS + 1));
+        assert!(!queue.has_enough());
+        for _ in 0..MIN_PACKETS {
+            assert!(queue.put(packet(100, 20)));
+        }
+        assert!(queue.has_enough());
+
+        // закрытой очереди пакеты больше не нужны
+        drop(receiver);
+        assert!(!queue.put(packet(100, 20)));
+        assert_eq!(queue.bytes(), 0);
+        assert!(queue.has_enough());
+    }
+
+    #[test]
+    fn has_enough_without_packet_duration() {
+        // у многих файлов длительность пакетов не указана, тогда хватает одного числа
+        let cancel = CancellationToken::new();
+        let (queue, _receiver) = packet_queue(Rational::new(1, 1000), &cancel);
+        for _ in 0..MIN_PACKETS {
+            assert!(queue.put(packet(100, 0)));
+        }
+        assert!(!queue.has_enough());
+        assert!(queue.put(packet(100, 0)));
+        assert!(queue.has_enough());
+    }
+}
@@ -0,0 +1,80 @@
+use crate::chapters::Chapters;
+use anyhow::{anyhow, Result};
+use ffmpeg::format::context::Input;
+use ffmpeg::rescale;
+use fftut::decode::start_time;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+
+const BAR_HEIGHT: u32 = 4;
+const MARKER_WIDTH: u32 = 2;
+const MARKER_HEIGHT: u32 = 8;
+
+// полоска прогресса внизу окна поверх видео, начала глав отмечены на ней засечками
+pub struct Progress {
+    // длительность файла в секундах, если контейнер её не знает, полоска не рисуется
+    duration: Option<f64>,
+    // время начала файла в секундах, позиция и главы отсчитываются от него, а полоска от нуля
+    start: f64,
+    pub chapters: Chapters,
+}
+
+impl Progress {
+    pub fn new(ictx: &Input) -> Progress {
+        // длительность у контекста всегда в AV_TIME_BASE
+        let duration = Some(ictx.duration())
+            .filter(|&duration| duration > 0)
+            .map(|duration| duration as f64 * f64::from(rescale::TIME_BASE));
+        Progress {
+            duration,
+            start: start_time(ictx) as f64 * f64::from(rescale::TIME_BASE),
+            chapters: Chapters::new(ictx),
+        }
+    }
+
+    // рисует поверх того что уже на канвасе, present() остаётся за вызывающим
+    pub fn draw(&self, canvas: &mut WindowCanvas, position: Option<f64>) -> Result<()> {
+        let duration = match self.duration {
+            Some(duration) => duration,
+            None => return Ok(()),
+        };
+        let (width, height) = canvas.output_size().map_err(|e| anyhow!(e))?;
+        let x = |seconds: f64| {
+            (((seconds - self.start) / duration).max(0.0).min(1.0) * f64::from(width)) as u32
+        };
+
+        let top = height.saturating_sub(BAR_HEIGHT) as i32;
+        canvas.set_draw_color(Color::RGB(64, 64, 64));
+        canvas
+            .fill_rect(Rect::new(0, top, width, BAR_HEIGHT))
+            .map_err(|e| anyhow!(e))?;
+
+        let played = position.map(x).unwrap_or(0);
+        if played > 0 {
+            canvas.set_draw_color(Color::RGB(255, 255, 255));
+            canvas
+                .fill_rect(Rect::new(0, top, played, BAR_HEIGHT))
+                .map_err(|e| anyhow!(e))?;
+        }
+
+        // засечку первой главы с начала файла не рисуем, она совпадает с началом полоски
+        canvas.set_draw_color(Color::RGB(255, 200, 0));
+        for chapter in self
+            .chapters
+            .iter()
+            .filter(|chapter| chapter.start > self.start)
+        {
+            let marker = x(chapter.start).saturating_sub(MARKER_WIDTH / 2) as i32;
+            canvas
+                .fill_rect(Rect::new(
+                    marker,
+                    height.saturating_sub(MARKER_HEIGHT) as i32,
+                    MARKER_WIDTH,
+                    MARKER_HEIGHT,
+                ))
+                .map_err(|e| anyhow!(e))?;
+        }
+        Ok(())
+    }
+}
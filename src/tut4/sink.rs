@@ -0,0 +1,135 @@
+use sdl2::audio::{AudioFormat, AudioQueue, AudioSpec};
+use std::cell::Cell;
+use std::time::Instant;
+
+// куда уходит звук: в звуковую карту или, с --headless, в никуда
+// методы те же что у AudioQueue, поэтому плееру всё равно куда он пишет
+pub enum AudioSink {
+    Device(AudioQueue<i16>),
+    Null(NullAudio),
+}
+
+impl AudioSink {
+    pub fn queue(&self, samples: &[i16]) {
+        match self {
+            AudioSink::Device(device) => {
+                device.queue(samples);
+            }
+            AudioSink::Null(null) => null.queue(samples),
+        }
+    }
+
+    pub fn clear(&self) {
+        match self {
+            AudioSink::Device(device) => device.clear(),
+            AudioSink::Null(null) => null.clear(),
+        }
+    }
+
+    // сколько байт ещё не проиграно
+    pub fn size(&self) -> u32 {
+        match self {
+            AudioSink::Device(device) => device.size(),
+            AudioSink::Null(null) => null.size(),
+        }
+    }
+
+    pub fn pause(&self) {
+        match self {
+            AudioSink::Device(device) => device.pause(),
+            AudioSink::Null(null) => null.pause(),
+        }
+    }
+
+    pub fn resume(&self) {
+        match self {
+            AudioSink::Device(device) => device.resume(),
+            AudioSink::Null(null) => null.resume(),
+        }
+    }
+
+    pub fn spec(&self) -> &AudioSpec {
+        match self {
+            AudioSink::Device(device) => device.spec(),
+            AudioSink::Null(null) => &null.spec,
+        }
+    }
+
+    pub fn wait_until_played(&self) {
+        match self {
+            AudioSink::Device(device) => fftut::audio::wait_until_played(device),
+            AudioSink::Null(null) => {
+                while null.size() > 0 {
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+            }
+        }
+    }
+}
+
+// звуковая карта без звука: сэмплы никуда не идут, но "играются" с той же скоростью,
+// что и на настоящем устройстве, так что часы и синхронизация работают как обычно
+pub struct NullAudio {
+    spec: AudioSpec,
+    // сколько байт ещё не проиграно
+    queued: Cell<f64>,
+    playing: Cell<bool>,
+    // когда queued последний раз уменьшался на проигранное
+    updated: Cell<Instant>,
+}
+
+impl NullAudio {
+    pub fn new(freq: i32, channels: u8) -> NullAudio {
+        NullAudio {
+            spec: AudioSpec {
+                freq,
+                format: AudioFormat::s16_sys(),
+                channels,
+                silence: 0,
+                samples: 4,
+                size: 0,
+            },
+            queued: Cell::new(0.0),
+            playing: Cell::new(false),
+            updated: Cell::new(Instant::now()),
+        }
+    }
+
+    fn queue(&self, samples: &[i16]) {
+        self.update();
+        self.queued
+            .set(self.queued.get() + (samples.len() * 2) as f64);
+    }
+
+    fn clear(&self) {
+        self.update();
+        self.queued.set(0.0);
+    }
+
+    fn size(&self) -> u32 {
+        self.update();
+        self.queued.get() as u32
+    }
+
+    fn pause(&self) {
+        self.update();
+        self.playing.set(false);
+    }
+
+    fn resume(&self) {
+        self.update();
+        self.playing.set(true);
+    }
+
+    // списываем то, что успело бы проиграться с прошлого раза
+    fn update(&self) {
+        let now = Instant::now();
+        if self.playing.get() {
+            let elapsed = (now - self.updated.get()).as_secs_f64();
+            let bytes_per_second = 2.0 * f64::from(self.spec.channels) * f64::from(self.spec.freq);
+            let played = elapsed * bytes_per_second;
+            self.queued.set((self.queued.get() - played).max(0.0));
+        }
+        self.updated.set(now);
+    }
+}
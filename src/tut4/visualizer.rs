@@ -0,0 +1,278 @@
+use anyhow::{anyhow, Result};
+use sdl2::audio::AudioSpec;
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::WindowCanvas;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+// сколько сэмплов идёт в FFT, должно быть степенью двойки
+const WINDOW: usize = 1024;
+// сколько столбиков у спектра
+const BARS: usize = 64;
+// всё что тише, спектр не показывает
+const FLOOR_DB: f32 = -80.0;
+
+// осциллограмма и спектр звука для файлов без видео
+// сэмплы приходят в том виде, в каком уходят в звуковую карту, а рисуется то,
+// что она играет прямо сейчас, то есть с поправкой на её очередь
+pub struct Visualizer {
+    // моно сэмплы от -1.0 до 1.0, столько, чтобы хватило на всю очередь звуковой карты
+    samples: VecDeque<f32>,
+    capacity: usize,
+    channels: usize,
+}
+
+impl Visualizer {
+    // queue_seconds это сколько звука плеер держит в очереди звуковой карты
+    // очередь может перерасти его на один кадр, поэтому помним на секунду больше
+    pub fn new(spec: &AudioSpec, queue_seconds: f64) -> Visualizer {
+        let capacity = (f64::from(spec.freq) * (queue_seconds + 1.0)) as usize + WINDOW;
+        Visualizer {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            channels: usize::from(spec.channels.max(1)),
+        }
+    }
+
+    // сэмплы, которые только что отданы звуковой карте, каналы сводятся в моно
+    pub fn push(&mut self, samples: &[i16]) {
+        for frame in samples.chunks_exact(self.channels) {
+            let sum: f32 = frame.iter().map(|&sample| f32::from(sample)).sum();
+            self.samples
+                .push_back(sum / self.channels as f32 / f32::from(i16::MAX));
+        }
+        while self.samples.len() > self.capacity {
+            self.samples.pop_front();
+        }
+    }
+
+    // после перемотки старые сэмплы к новой позиции отношения не имеют
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    // queued это сколько сэмплов на канал ещё лежит в очереди звуковой карты
+    // осциллограмма рисуется в верхней половине окна, спектр в нижней
+    // present() остаётся за вызывающим
+    pub fn draw(&self, canvas: &mut WindowCanvas, queued: usize) -> Result<()> {
+        let window = self.playing(queued);
+        let (width, height) = canvas.output_size().map_err(|e| anyhow!(e))?;
+        let half = height / 2;
+
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+
+        // осциллограмма: по сэмплу на каждый столбец пикселей
+        let middle = half as f32 / 2.0;
+        let points = (0..width)
+            .map(|x| {
+                let sample = window[x as usize * WINDOW / width as usize];
+                Point::new(x as i32, (middle - sample * middle * 0.9) as i32)
+            })
+            .collect::<Vec<_>>();
+        canvas.set_draw_color(Color::RGB(0, 255, 128));
+        canvas
+            .draw_lines(points.as_slice())
+            .map_err(|e| anyhow!(e))?;
+
+        // спектр: столбики по логарифмической шкале частот, высота в децибелах
+        let spectrum = spectrum(&window);
+        let bar_width = (width / BARS as u32).max(1);
+        canvas.set_draw_color(Color::RGB(0, 160, 255));
+        for bar in 0..BARS {
+            let from = bin_for_bar(bar);
+            let to = bin_for_bar(bar + 1).max(from + 1);
+            let level = spectrum[from..to].iter().cloned().fold(FLOOR_DB, f32::max);
+            let bar_height = ((level - FLOOR_DB) / -FLOOR_DB * half as f32) as u32;
+            if bar_height == 0 {
+                continue;
+            }
+            canvas
+                .fill_rect(Rect::new(
+                    (bar as u32 * bar_width) as i32,
+                    (height - bar_height) as i32,
+                    bar_width.saturating_sub(1).max(1),
+                    bar_height,
+                ))
+                .map_err(|e| anyhow!(e))?;
+        }
+        Ok(())
+    }
+
+    // последние WINDOW сэмплов перед тем местом, которое звуковая карта играет сейчас
+    // если очередь длиннее истории, показываем самое старое что есть, а не тишину
+    fn playing(&self, queued: usize) -> Vec<f32> {
+        let queued = queued.min(self.samples.len().saturating_sub(WINDOW));
+        let end = self.samples.len() - queued;
+        let start = end.saturating_sub(WINDOW);
+        let mut window = vec![0.0; WINDOW - (end - start)];
+        window.extend(self.samples.range(start..end));
+        window
+    }
+}
+
+// первый бин FFT для столбика, нижние частоты получают меньше бинов чем верхние
+fn bin_for_bar(bar: usize) -> usize {
+    let bins = (WINDOW / 2) as f32;
+    (bins.powf(bar as f32 / BARS as f32) as usize).min(WINDOW / 2)
+}
+
+// амплитуды первой половины спектра в децибелах, окно Ханна убирает размытие спектра
+fn spectrum(samples: &[f32]) -> Vec<f32> {
+    let mut buffer = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &sample)| {
+            let hann = 0.5 - 0.5 * (2.0 * PI * i as f32 / (WINDOW - 1) as f32).cos();
+            (sample * hann, 0.0)
+        })
+        .collect::<Vec<_>>();
+    fft(&mut buffer);
+
+    // синусоида полной громкости с окном Ханна даёт в своём бине WINDOW / 4
+    let full_scale = WINDOW as f32 / 4.0;
+    buffer[..WINDOW / 2]
+        .iter()
+        .map(|&(re, im)| {
+            let magnitude = (re * re + im * im).sqrt() / full_scale;
+            (20.0 * magnitude.max(1e-9).log10()).max(FLOOR_DB)
+        })
+        .collect()
+}
+
+// классическое итеративное БПФ Кули-Тьюки по основанию 2, комплексные числа это (re, im)
+fn fft(buffer: &mut [(f32, f32)]) {
+    let n = buffer.len();
+
+    // перестановка с обращением битов индекса
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buffer.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_re, w_im) = ((angle * k as f32).cos(), (angle * k as f32).sin());
+                let (a_re, a_im) = buffer[start + k];
+                let (b_re, b_im) = buffer[start + k + len / 2];
+                let (t_re, t_im) = (b_re * w_re - b_im * w_im, b_re * w_im + b_im * w_re);
+                buffer[start + k] = (a_re + t_re, a_im + t_im);
+                buffer[start + k + len / 2] = (a_re - t_re, a_im - t_im);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::audio::AudioFormat;
+
+    fn spec(freq: i32, channels: u8) -> AudioSpec {
+        AudioSpec {
+            freq,
+            format: AudioFormat::s16_sys(),
+            channels,
+            silence: 0,
+            samples: 4,
+            size: 0,
+        }
+    }
+
+    // синусоида ровно в бине bin, чтобы вся энергия попала в него
+    fn sine(bin: usize, amplitude: f32) -> Vec<f32> {
+        (0..WINDOW)
+            .map(|i| amplitude * (2.0 * PI * bin as f32 * i as f32 / WINDOW as f32).sin())
+            .collect()
+    }
+
+    fn loudest(spectrum: &[f32]) -> usize {
+        (0..spectrum.len())
+            .max_by(|&a, &b| spectrum[a].partial_cmp(&spectrum[b]).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn playing_skips_what_is_still_queued() {
+        let mut visualizer = Visualizer::new(&spec(8000, 1), 0.5);
+        let samples = (0..3000).map(|i| i as i16).collect::<Vec<_>>();
+        visualizer.push(&samples);
+
+        let window = visualizer.playing(500);
+        assert_eq!(window.len(), WINDOW);
+        // последний сэмпл окна тот, что звуковая карта играет сейчас
+        assert_eq!(window[WINDOW - 1], 2499.0 / f32::from(i16::MAX));
+        assert_eq!(window[0], (2500 - WINDOW) as f32 / f32::from(i16::MAX));
+    }
+
+    #[test]
+    fn playing_pads_start_with_silence() {
+        let mut visualizer = Visualizer::new(&spec(8000, 2), 0.5);
+        // стерео сводится в моно
+        visualizer.push(&[i16::MAX, i16::MAX, i16::MAX, -i16::MAX]);
+
+        let window = visualizer.playing(0);
+        assert_eq!(window.len(), WINDOW);
+        assert!(window[..WINDOW - 2].iter().all(|&sample| sample == 0.0));
+        assert_eq!(window[WINDOW - 2..], [1.0, 0.0]);
+    }
+
+    #[test]
+    fn playing_clamps_queue_longer_than_history() {
+        let mut visualizer = Visualizer::new(&spec(8000, 1), 0.5);
+        let samples = (0..30000)
+            .map(|i| (i % 1000) as i16 + 1)
+            .collect::<Vec<_>>();
+        visualizer.push(&samples);
+
+        // в истории 8000 * 1.5 + WINDOW сэмплов, очередь в десять секунд её перерастает
+        let window = visualizer.playing(80000);
+        assert_eq!(window.len(), WINDOW);
+        assert!(window.iter().all(|&sample| sample > 0.0));
+    }
+
+    #[test]
+    fn fft_of_sine_peaks_in_its_bin() {
+        let mut buffer = sine(37, 1.0)
+            .into_iter()
+            .map(|sample| (sample, 0.0))
+            .collect::<Vec<_>>();
+        fft(&mut buffer);
+        let magnitudes = buffer[..WINDOW / 2]
+            .iter()
+            .map(|&(re, im)| (re * re + im * im).sqrt())
+            .collect::<Vec<_>>();
+        assert_eq!(loudest(&magnitudes), 37);
+        // без окна вся амплитуда синусоиды даёт WINDOW / 2 в одном бине
+        assert!((magnitudes[37] - WINDOW as f32 / 2.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn spectrum_of_full_scale_sine_is_zero_db() {
+        let spectrum = spectrum(&sine(100, 1.0));
+        assert_eq!(spectrum.len(), WINDOW / 2);
+        assert_eq!(loudest(&spectrum), 100);
+        assert!(spectrum[100].abs() < 0.1);
+        // далеко от синусоиды только пол
+        assert_eq!(spectrum[400], FLOOR_DB);
+    }
+
+    #[test]
+    fn spectrum_of_silence_is_floor() {
+        let spectrum = spectrum(&[0.0; WINDOW]);
+        assert!(spectrum.iter().all(|&level| level == FLOOR_DB));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::message::{Worker, WorkerEvent};
+use anyhow::Result;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// поток конвейера, который умеет сказать, что закончился, не дожидаясь join()
+// JoinHandle ждать с таймаутом не умеет, а бросить поток, отдав join() другому потоку,
+// значит оставить тот поток висеть вместе с ним
+pub struct WorkerThread {
+    handle: JoinHandle<()>,
+    done: Arc<Done>,
+}
+
+struct Done {
+    finished: Mutex<bool>,
+    changed: Condvar,
+}
+
+// отмечает поток законченным при любом выходе, в том числе при панике
+struct DoneGuard(Arc<Done>);
+
+impl Drop for DoneGuard {
+    fn drop(&mut self) {
+        *self.0.finished.lock().unwrap() = true;
+        self.0.changed.notify_all();
+    }
+}
+
+// запускаем поток конвейера, его ошибка уходит в очередь событий, а не теряется в JoinHandle
+pub fn spawn_worker<F>(worker: Worker, events: Sender<WorkerEvent>, body: F) -> WorkerThread
+where
+    F: FnOnce() -> Result<()> + Send + 'static,
+{
+    let done = Arc::new(Done {
+        finished: Mutex::new(false),
+        changed: Condvar::new(),
+    });
+    let guard = DoneGuard(done.clone());
+    let handle = thread::spawn(move || {
+        let _guard = guard;
+        if let Err(error) = body() {
+            events
+                .send(WorkerEvent::Failed { worker, error })
+                .unwrap_or(());
+        }
+    });
+    WorkerThread { handle, done }
+}
+
+impl WorkerThread {
+    pub fn is_finished(&self) -> bool {
+        *self.done.finished.lock().unwrap()
+    }
+
+    // Err если поток запаниковал
+    pub fn join(self) -> thread::Result<()> {
+        self.handle.join()
+    }
+
+    // ждём поток не дольше timeout; зависший поток возвращается обратно в Err,
+    // его можно дождаться позже, когда он всё-таки закончится
+    pub fn join_timeout(self, timeout: Duration) -> Result<thread::Result<()>, WorkerThread> {
+        let deadline = Instant::now() + timeout;
+        let mut finished = self.done.finished.lock().unwrap();
+        while !*finished {
+            let now = Instant::now();
+            if now >= deadline {
+                drop(finished);
+                return Err(self);
+            }
+            finished = self
+                .done
+                .changed
+                .wait_timeout(finished, deadline - now)
+                .unwrap()
+                .0;
+        }
+        drop(finished);
+        Ok(self.join())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::CancellationToken;
+    use crate::frame_queue::{frame_queue, QueueItem};
+    use anyhow::anyhow;
+    use std::sync::mpsc::channel;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    struct Item;
+
+    impl QueueItem for Item {
+        fn size(&self) -> usize {
+            1
+        }
+    }
+
+    #[test]
+    fn cancelled_worker_joins_in_time() {
+        let cancel = CancellationToken::new();
+        let (sender, _queue) = frame_queue(1, usize::MAX, &cancel);
+        let (events_tx, events_rx) = channel();
+        // декодер упирается в полную очередь, которую никто не читает
+        let thread = spawn_worker(Worker::Video, events_tx, move || {
+            while sender.send(Item) {}
+            Ok(())
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert!(!thread.is_finished());
+
+        cancel.cancel();
+        match thread.join_timeout(WAIT) {
+            Ok(result) => assert!(result.is_ok()),
+            Err(_) => panic!("cancelled worker didn't stop"),
+        }
+        assert!(events_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn stuck_worker_times_out() {
+        let (release_tx, release_rx) = channel::<()>();
+        let (events_tx, _events_rx) = channel();
+        // поток, который отмену не слышит
+        let thread = spawn_worker(Worker::Demux, events_tx, move || {
+            release_rx.recv().unwrap_or(());
+            Ok(())
+        });
+
+        let started = Instant::now();
+        let thread = match thread.join_timeout(Duration::from_millis(50)) {
+            Ok(_) => panic!("stuck worker joined"),
+            Err(thread) => thread,
+        };
+        assert!(started.elapsed() < WAIT);
+        assert!(!thread.is_finished());
+
+        // зависший поток можно дождаться позже
+        release_tx.send(()).unwrap();
+        match thread.join_timeout(WAIT) {
+            Ok(result) => assert!(result.is_ok()),
+            Err(_) => panic!("released worker didn't stop"),
+        }
+    }
+
+    #[test]
+    fn error_goes_to_events() {
+        let (events_tx, events_rx) = channel();
+        let thread = spawn_worker(Worker::Audio, events_tx, || Err(anyhow!("broken")));
+        assert!(thread.join().is_ok());
+        match events_rx.recv_timeout(WAIT) {
+            Ok(WorkerEvent::Failed { worker, error }) => {
+                assert!(matches!(worker, Worker::Audio));
+                assert_eq!(error.to_string(), "broken");
+            }
+            _ => panic!("expected a failure event"),
+        }
+    }
+
+    #[test]
+    fn panicked_worker_is_finished() {
+        let (events_tx, _events_rx) = channel();
+        let thread = spawn_worker(Worker::Video, events_tx, || panic!("worker panic"));
+        match thread.join_timeout(WAIT) {
+            Ok(result) => assert!(result.is_err()),
+            Err(_) => panic!("panicked worker isn't finished"),
+        }
+    }
+}